@@ -1,7 +1,6 @@
-use anyhow::{Context, Error as E, Result};
-use clap::{Parser, ValueEnum};
+use anyhow::{bail, Context, Error as E, Result};
+use clap::{Parser, Subcommand, ValueEnum};
 use serde::Deserialize;
-use serde_yaml;
 use std::fs;
 use std::path::Path;
 
@@ -36,18 +35,231 @@ impl std::fmt::Display for QueueType {
     }
 }
 
+/// Supported log output formats
+#[derive(ValueEnum, Debug, Clone, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One JSON object per line (default)
+    #[default]
+    Json,
+    /// Multi-line human readable output
+    Pretty,
+    /// Single-line human readable output
+    Compact,
+}
+
+impl std::fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogFormat::Json => write!(f, "json"),
+            LogFormat::Pretty => write!(f, "pretty"),
+            LogFormat::Compact => write!(f, "compact"),
+        }
+    }
+}
+
+/// How often the log file is rotated
+#[derive(ValueEnum, Debug, Clone, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    /// Never rotate the log file
+    Never,
+    /// Start a new log file every hour
+    Hourly,
+    /// Start a new log file every day (default)
+    #[default]
+    Daily,
+}
+
+impl std::fmt::Display for LogRotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogRotation::Never => write!(f, "never"),
+            LogRotation::Hourly => write!(f, "hourly"),
+            LogRotation::Daily => write!(f, "daily"),
+        }
+    }
+}
+
+/// How prompt and completion content is written to logs
+#[derive(ValueEnum, Debug, Clone, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogContent {
+    /// Only message counts, roles and lengths (default)
+    #[default]
+    Summary,
+    /// A SHA-256 digest of each message instead of its content
+    Hash,
+    /// Full content with emails, phone numbers, secrets and custom patterns masked
+    Masked,
+}
+
+impl std::fmt::Display for LogContent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogContent::Summary => write!(f, "summary"),
+            LogContent::Hash => write!(f, "hash"),
+            LogContent::Masked => write!(f, "masked"),
+        }
+    }
+}
+
+/// gRPC services a listener can expose; health and reflection are always served
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ListenerService {
+    Api,
+    Admin,
+}
+
+/// One gRPC listener, bound to either a TCP address or a Unix domain socket
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListenerConfig {
+    /// TCP "host:port" to bind
+    #[serde(default)]
+    pub address: Option<String>,
+    /// Unix domain socket path to bind instead of a TCP address
+    #[serde(default)]
+    pub unix_socket: Option<String>,
+    /// Terminate TLS using the tls_* settings (TCP only)
+    #[serde(default)]
+    pub tls: bool,
+    /// Services to expose; only the API unless admin is listed explicitly
+    #[serde(default = "default_listener_services")]
+    pub services: Vec<ListenerService>,
+}
+
+/// A model served under its own name; unset fields fall back to the top-level settings
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModelConfig {
+    /// Name clients select the model by
+    pub name: String,
+    #[serde(default)]
+    pub model_family: Option<String>,
+    pub model_path: String,
+    #[serde(default)]
+    pub dtype: Option<String>,
+    #[serde(default)]
+    pub quantize: Option<String>,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub chat_template: Option<String>,
+    #[serde(default)]
+    pub sample_len: Option<usize>,
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub top_p: Option<f64>,
+    #[serde(default)]
+    pub top_k: Option<usize>,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub repeat_penalty: Option<f32>,
+    #[serde(default)]
+    pub repeat_last_n: Option<usize>,
+}
+
+fn default_listener_services() -> Vec<ListenerService> {
+    vec![ListenerService::Api]
+}
+
+/// Tasks run instead of starting the server
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Check a model without loading its weights and report what Cylon would serve
+    Inspect {
+        /// Model directory, GGUF file or Hugging Face repo id
+        model_path: String,
+
+        /// Model family; detected from the model config when omitted
+        #[arg(long)]
+        model_family: Option<String>,
+    },
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct CliArgs {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(long, env = "CYLON_DEBUG", default_value_t = false)]
     debug: bool,
 
+    /// Log filter directives (e.g. "info,cylon=debug"). Falls back to RUST_LOG, then --debug.
+    #[arg(long, env = "CYLON_LOG_LEVEL")]
+    log_level: Option<String>,
+
+    #[arg(long, env = "CYLON_LOG_FORMAT", default_value_t = LogFormat::Json)]
+    log_format: LogFormat,
+
+    /// Also write logs to this file, rotated according to --log-rotation.
+    #[arg(long, env = "CYLON_LOG_FILE")]
+    log_file: Option<String>,
+
+    #[arg(long, env = "CYLON_LOG_ROTATION", default_value_t = LogRotation::Daily)]
+    log_rotation: LogRotation,
+
+    /// How prompt and completion content appears in logs.
+    #[arg(long, env = "CYLON_LOG_CONTENT", default_value_t = LogContent::Summary)]
+    log_content: LogContent,
+
+    /// Additional regex masked in logged content when --log-content=masked. May be repeated.
+    #[arg(long = "log-redaction-pattern", env = "CYLON_LOG_REDACTION_PATTERN")]
+    log_redaction_patterns: Vec<String>,
+
     #[arg(long, env = "CYLON_LISTEN_ADDRESS", default_value = "127.0.0.1")]
     listen_address: String,
 
     #[arg(long, env = "CYLON_LISTEN_PORT", default_value = "8080")]
     listen_port: String,
 
+    /// Also serve on this Unix domain socket, e.g. for a sidecar on the same host.
+    /// Without explicit listeners, this is the only place the admin service is served.
+    #[arg(long, env = "CYLON_LISTEN_UNIX_SOCKET")]
+    listen_unix_socket: Option<String>,
+
+    /// PEM certificate chain for the gRPC listener. Enables TLS together with --tls-key.
+    #[arg(long, env = "CYLON_TLS_CERT", requires = "tls_key")]
+    tls_cert: Option<String>,
+
+    /// PEM private key for --tls-cert.
+    #[arg(long, env = "CYLON_TLS_KEY", requires = "tls_cert")]
+    tls_key: Option<String>,
+
+    /// PEM CA bundle used to verify client certificates. Enables mutual TLS.
+    #[arg(long, env = "CYLON_TLS_CLIENT_CA", requires = "tls_cert")]
+    tls_client_ca: Option<String>,
+
+    /// Client certificate common name allowed to connect. May be repeated; any verified client is allowed when unset.
+    #[arg(long = "tls-allowed-cn", env = "CYLON_TLS_ALLOWED_CN", requires = "tls_client_ca")]
+    tls_allowed_cns: Vec<String>,
+
+    /// Seconds between checks of the TLS files for changes.
+    #[arg(long, env = "CYLON_TLS_RELOAD_INTERVAL", default_value_t = 10)]
+    tls_reload_interval: u64,
+
+    /// YAML file of hashed API keys and their permissions. Requests are not authenticated when unset.
+    #[arg(long, env = "CYLON_API_KEYS_FILE")]
+    api_keys_file: Option<String>,
+
+    /// Serve the admin service without authentication when no API keys file is set.
+    #[arg(long, env = "CYLON_ADMIN_ALLOW_UNAUTHENTICATED", default_value_t = false)]
+    admin_allow_unauthenticated: bool,
+
+    /// Disable the HTTP listener serving /metrics.
+    #[arg(long, env = "CYLON_HTTP_DISABLED", default_value_t = false)]
+    http_disabled: bool,
+
+    #[arg(long, env = "CYLON_HTTP_LISTEN_PORT", default_value = "9090")]
+    http_listen_port: String,
+
+    /// OTLP gRPC endpoint to export traces to (e.g. http://localhost:4317). Tracing export is disabled when unset.
+    #[arg(long, env = "CYLON_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+
     #[arg(long, env = "CYLON_QUEUE_DISABLED", default_value_t = false)]
     queue_disabled: bool,
 
@@ -61,6 +273,14 @@ struct CliArgs {
     #[arg(long, env = "CYLON_RESULT_CACHE_TTL", default_value_t = 3600)]
     result_cache_ttl: i64,
 
+    /// Seconds to wait for running and queued jobs to finish on shutdown or drain.
+    #[arg(long, env = "CYLON_SHUTDOWN_GRACE_PERIOD", default_value_t = 30)]
+    shutdown_grace_period: u64,
+
+    /// Cancel queued jobs immediately on shutdown instead of letting them run within the grace period.
+    #[arg(long, env = "CYLON_SHUTDOWN_CANCEL_QUEUED", default_value_t = false)]
+    shutdown_cancel_queued: bool,
+
     #[arg(long, env = "CYLON_MODEL_FAMILY", default_value = "llama")]
     model_family: String,
 
@@ -74,9 +294,21 @@ struct CliArgs {
     #[arg(long, env = "CYLON_CONFIG_FILE")]
     config_file: Option<String>,
 
-    /// The temperature used to generate samples.
-    #[arg(long, env = "CYLON_TEMPERATURE", default_value_t = 0.0)]
-    temperature: f64,
+    /// Load each model on its first request instead of at startup.
+    #[arg(long, env = "CYLON_LAZY_LOAD", default_value_t = false)]
+    lazy_load: bool,
+
+    /// Unload a model after this many seconds without requests. 0 keeps models loaded.
+    #[arg(long, env = "CYLON_MODEL_IDLE_TIMEOUT", default_value_t = 0)]
+    model_idle_timeout: u64,
+
+    /// Megabytes of model weights to keep loaded; least recently used models are unloaded to stay within it.
+    #[arg(long, env = "CYLON_MODEL_MEMORY_BUDGET")]
+    model_memory_budget: Option<u64>,
+
+    /// The temperature used to generate samples. Defaults to the model's generation_config.json, else 0 (greedy).
+    #[arg(long, env = "CYLON_TEMPERATURE")]
+    temperature: Option<f64>,
 
     /// Nucleus sampling probability cutoff.
     #[arg(long, env = "CYLON_TOP_P")]
@@ -102,46 +334,132 @@ struct CliArgs {
     #[arg(long, env = "CYLON_SYSTEM_PROMPT", default_value_t = String::from("You are a helpful assistant."))]
     system_prompt: String,
 
+    /// Jinja chat template file to use instead of the model's own.
+    #[arg(long, env = "CYLON_CHAT_TEMPLATE")]
+    chat_template: Option<String>,
+
     /// Use different dtype than f16
     #[arg(long, env = "CYLON_DTYPE", default_value = "f16")]
     dtype: Option<String>,
 
+    /// Quantize safetensors weights while loading, e.g. q8_0 or q4_k.
+    #[arg(long, env = "CYLON_QUANTIZE")]
+    quantize: Option<String>,
+
     #[arg(long, env = "CYLON_USE_FLASH_ATTN", default_value_t = false)]
     use_flash_attn: bool,
 
-    /// Penalty to be applied for repeating tokens, 1. means no penalty.
-    #[arg(long, env = "CYLON_REPEAT_PENALTY", default_value_t = 1.0)]
-    repeat_penalty: f32,
+    /// Penalty to be applied for repeating tokens, 1. means no penalty. Defaults to the model's generation_config.json, else 1.
+    #[arg(long, env = "CYLON_REPEAT_PENALTY")]
+    repeat_penalty: Option<f32>,
 
     /// The context size to consider for the repeat penalty.
     #[arg(long, env = "CYLON_REPEAT_LAST_N", default_value_t = 128)]
     repeat_last_n: usize,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct CylonConfig {
+    /// Subcommand given on the command line, never read from YAML
+    #[serde(skip)]
+    pub command: Option<Command>,
     pub debug: bool,
+    #[serde(default)]
+    pub log_level: Option<String>,
+    #[serde(default)]
+    pub log_format: LogFormat,
+    #[serde(default)]
+    pub log_file: Option<String>,
+    #[serde(default)]
+    pub log_rotation: LogRotation,
+    #[serde(default)]
+    pub log_content: LogContent,
+    #[serde(default)]
+    pub log_redaction_patterns: Vec<String>,
     pub listen_address: String,
     pub listen_port: String,
+    #[serde(default)]
+    pub listen_unix_socket: Option<String>,
+    /// Explicit listeners; when empty, listen_address:listen_port and listen_unix_socket are used
+    #[serde(default)]
+    pub listeners: Vec<ListenerConfig>,
+    #[serde(default)]
+    pub tls_cert: Option<String>,
+    #[serde(default)]
+    pub tls_key: Option<String>,
+    #[serde(default)]
+    pub tls_client_ca: Option<String>,
+    #[serde(default)]
+    pub tls_allowed_cns: Vec<String>,
+    #[serde(default = "default_tls_reload_interval")]
+    pub tls_reload_interval: u64,
+    #[serde(default)]
+    pub api_keys_file: Option<String>,
+    #[serde(default)]
+    pub admin_allow_unauthenticated: bool,
+    #[serde(default)]
+    pub http_disabled: bool,
+    #[serde(default = "default_http_listen_port")]
+    pub http_listen_port: String,
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
     pub queue_disabled: bool,
     pub queue_type: QueueType,
     pub queue_buffer_size: usize,
     pub result_cache_ttl: i64,
+    #[serde(default = "default_shutdown_grace_period")]
+    pub shutdown_grace_period: u64,
+    #[serde(default)]
+    pub shutdown_cancel_queued: bool,
     pub model_family: String,
     pub model_path: String,
-    pub temperature: f64,
+    /// Named models; when empty, model_family and model_path describe the only model
+    #[serde(default)]
+    pub models: Vec<ModelConfig>,
+    /// Model used when a request does not name one; defaults to the first entry in models
+    #[serde(default)]
+    pub default_model: Option<String>,
+    #[serde(default)]
+    pub lazy_load: bool,
+    #[serde(default)]
+    pub model_idle_timeout: u64,
+    /// In megabytes
+    #[serde(default)]
+    pub model_memory_budget: Option<u64>,
+    /// Unset sampling options fall back to the model's `generation_config.json`
+    #[serde(default)]
+    pub temperature: Option<f64>,
     pub top_p: Option<f64>,
     pub top_k: Option<usize>,
     pub seed: u64,
     pub sample_len: usize,
     pub enable_kv_cache: bool,
     pub system_prompt: String,
+    /// Jinja file replacing the chat template shipped with the model
+    #[serde(default)]
+    pub chat_template: Option<String>,
     pub dtype: Option<String>,
+    /// Weight type to quantize safetensors checkpoints to while loading
+    #[serde(default)]
+    pub quantize: Option<String>,
     pub use_flash_attn: bool,
-    pub repeat_penalty: f32,
+    #[serde(default)]
+    pub repeat_penalty: Option<f32>,
     pub repeat_last_n: usize,
 }
 
+fn default_http_listen_port() -> String {
+    String::from("9090")
+}
+
+fn default_tls_reload_interval() -> u64 {
+    10
+}
+
+fn default_shutdown_grace_period() -> u64 {
+    30
+}
+
 impl CylonConfig {
     pub fn new() -> Result<CylonConfig, E> {
         let args = CliArgs::parse();
@@ -151,18 +469,45 @@ impl CylonConfig {
             let content = fs::read_to_string(config_path).with_context(|| {
                 format!("Config file not found: {}", config_path.to_string_lossy())
             })?;
-            serde_yaml::from_str(&content).with_context(|| "Failed to deserialize YAML config")?
+            let config: CylonConfig = serde_yaml::from_str(&content).with_context(|| "Failed to deserialize YAML config")?;
+            CylonConfig { command: args.command, ..config }
         } else {
             CylonConfig {
+                command: args.command,
                 debug: args.debug,
+                log_level: args.log_level,
+                log_format: args.log_format,
+                log_file: args.log_file,
+                log_rotation: args.log_rotation,
+                log_content: args.log_content,
+                log_redaction_patterns: args.log_redaction_patterns,
                 listen_address: args.listen_address,
                 listen_port: args.listen_port,
+                listen_unix_socket: args.listen_unix_socket,
+                listeners: Vec::new(),
+                tls_cert: args.tls_cert,
+                tls_key: args.tls_key,
+                tls_client_ca: args.tls_client_ca,
+                tls_allowed_cns: args.tls_allowed_cns,
+                tls_reload_interval: args.tls_reload_interval,
+                api_keys_file: args.api_keys_file,
+                admin_allow_unauthenticated: args.admin_allow_unauthenticated,
+                http_disabled: args.http_disabled,
+                http_listen_port: args.http_listen_port,
+                otlp_endpoint: args.otlp_endpoint,
                 queue_disabled: args.queue_disabled,
                 queue_type: args.queue_type,
                 queue_buffer_size: args.queue_buffer_size,
                 result_cache_ttl: args.result_cache_ttl,
+                shutdown_grace_period: args.shutdown_grace_period,
+                shutdown_cancel_queued: args.shutdown_cancel_queued,
                 model_family: args.model_family,
                 model_path: args.model_path,
+                models: Vec::new(),
+                default_model: None,
+                lazy_load: args.lazy_load,
+                model_idle_timeout: args.model_idle_timeout,
+                model_memory_budget: args.model_memory_budget,
                 temperature: args.temperature,
                 top_p: args.top_p,
                 top_k: args.top_k,
@@ -170,7 +515,9 @@ impl CylonConfig {
                 sample_len: args.sample_len,
                 enable_kv_cache: args.enable_kv_cache,
                 system_prompt: args.system_prompt,
+                chat_template: args.chat_template,
                 dtype: args.dtype,
+                quantize: args.quantize,
                 use_flash_attn: args.use_flash_attn,
                 repeat_penalty: args.repeat_penalty,
                 repeat_last_n: args.repeat_last_n,
@@ -179,4 +526,222 @@ impl CylonConfig {
 
         Ok(yaml_config)
     }
+
+    /// The gRPC listeners to bind, derived from listen_address/listen_port when none are configured
+    pub fn listeners(&self) -> Vec<ListenerConfig> {
+        if !self.listeners.is_empty() {
+            return self.listeners.clone();
+        }
+
+        let mut listeners = vec![ListenerConfig {
+            address: Some(format!("{}:{}", self.listen_address, self.listen_port)),
+            unix_socket: None,
+            tls: self.tls_cert.is_some(),
+            services: default_listener_services(),
+        }];
+
+        if let Some(unix_socket) = &self.listen_unix_socket {
+            listeners.push(ListenerConfig {
+                address: None,
+                unix_socket: Some(unix_socket.clone()),
+                tls: false,
+                // Local only, so the admin service is reachable without exposing it on the network
+                services: vec![ListenerService::Api, ListenerService::Admin],
+            });
+        }
+
+        listeners
+    }
+
+    /// Name and effective config of each served model, in configuration order
+    pub fn models(&self) -> Result<Vec<(String, CylonConfig)>> {
+        if self.models.is_empty() {
+            // Name the only model after its directory, GGUF file or Hugging Face repo,
+            // falling back to the family
+            let path = Path::new(&self.model_path);
+            let name = match path.extension() {
+                Some(ext) if ext.eq_ignore_ascii_case("gguf") => path.file_stem(),
+                _ => path.file_name(),
+            };
+            let name = name
+                .map(|name| name.to_string_lossy())
+                .map(|name| name.split('@').next().unwrap_or_default().to_string())
+                .unwrap_or_else(|| self.model_family.clone());
+            return Ok(vec![(name, self.clone())]);
+        }
+
+        let mut models: Vec<(String, CylonConfig)> = Vec::with_capacity(self.models.len());
+        for model in &self.models {
+            if model.name.is_empty() {
+                bail!("Model names must not be empty");
+            }
+            if models.iter().any(|(name, _)| *name == model.name) {
+                bail!("Model {} is configured more than once", model.name);
+            }
+
+            let mut config = self.clone();
+            config.models = Vec::new();
+            config.default_model = None;
+            config.model_path = model.model_path.clone();
+            if let Some(model_family) = &model.model_family {
+                config.model_family = model_family.clone();
+            }
+            if model.dtype.is_some() {
+                config.dtype = model.dtype.clone();
+            }
+            if model.quantize.is_some() {
+                config.quantize = model.quantize.clone();
+            }
+            if let Some(system_prompt) = &model.system_prompt {
+                config.system_prompt = system_prompt.clone();
+            }
+            if model.chat_template.is_some() {
+                config.chat_template = model.chat_template.clone();
+            }
+            config.sample_len = model.sample_len.unwrap_or(config.sample_len);
+            config.temperature = model.temperature.or(config.temperature);
+            config.top_p = model.top_p.or(config.top_p);
+            config.top_k = model.top_k.or(config.top_k);
+            config.seed = model.seed.unwrap_or(config.seed);
+            config.repeat_penalty = model.repeat_penalty.or(config.repeat_penalty);
+            config.repeat_last_n = model.repeat_last_n.unwrap_or(config.repeat_last_n);
+
+            models.push((model.name.clone(), config));
+        }
+
+        if let Some(default_model) = &self.default_model
+            && !models.iter().any(|(name, _)| name == default_model)
+        {
+            bail!("Default model {} is not configured", default_model);
+        }
+
+        Ok(models)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "
+debug: false
+listen_address: 127.0.0.1
+listen_port: \"8080\"
+queue_disabled: false
+queue_type: local
+queue_buffer_size: 10
+result_cache_ttl: 3600
+model_family: llama
+model_path: /models/Llama-3.2-1B
+temperature: 0.7
+seed: 1
+sample_len: 100
+enable_kv_cache: true
+system_prompt: Be brief.
+use_flash_attn: false
+repeat_penalty: 1.1
+repeat_last_n: 64
+";
+
+    fn config(extra: &str) -> CylonConfig {
+        serde_yaml::from_str(&format!("{BASE}{extra}")).unwrap()
+    }
+
+    fn names(models: &[(String, CylonConfig)]) -> Vec<&str> {
+        models.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    #[test]
+    fn names_the_only_model_after_its_path() {
+        let mut config = config("");
+        assert_eq!(names(&config.models().unwrap()), ["Llama-3.2-1B"]);
+
+        config.model_path = String::from("/models/llama-q4_k_m.GGUF");
+        assert_eq!(names(&config.models().unwrap()), ["llama-q4_k_m"]);
+
+        config.model_path = String::from("meta-llama/Llama-3.2-1B@main");
+        assert_eq!(names(&config.models().unwrap()), ["Llama-3.2-1B"]);
+
+        config.model_path = String::from("/");
+        assert_eq!(names(&config.models().unwrap()), ["llama"]);
+    }
+
+    #[test]
+    fn named_models_fall_back_to_top_level_settings() {
+        let config = config("
+models:
+  - name: small
+    model_path: /models/small
+  - name: chat
+    model_family: mistral
+    model_path: /models/chat
+    system_prompt: Be kind.
+    temperature: 0.2
+    sample_len: 50
+    repeat_last_n: 32
+default_model: chat
+");
+        let models = config.models().unwrap();
+        assert_eq!(names(&models), ["small", "chat"]);
+
+        let small = &models[0].1;
+        assert_eq!(small.model_family, "llama");
+        assert_eq!(small.model_path, "/models/small");
+        assert_eq!(small.system_prompt, "Be brief.");
+        assert_eq!(small.temperature, Some(0.7));
+        assert_eq!(small.sample_len, 100);
+        assert_eq!(small.repeat_penalty, Some(1.1));
+        assert!(small.models.is_empty());
+        assert_eq!(small.default_model, None);
+
+        let chat = &models[1].1;
+        assert_eq!(chat.model_family, "mistral");
+        assert_eq!(chat.system_prompt, "Be kind.");
+        assert_eq!(chat.temperature, Some(0.2));
+        assert_eq!(chat.sample_len, 50);
+        assert_eq!(chat.repeat_last_n, 32);
+        assert_eq!(chat.repeat_penalty, Some(1.1));
+    }
+
+    #[test]
+    fn rejects_empty_duplicate_and_unknown_default_models() {
+        let empty = config("models:\n  - name: \"\"\n    model_path: /models/a\n");
+        assert_eq!(empty.models().unwrap_err().to_string(), "Model names must not be empty");
+
+        let duplicate = config("models:\n  - name: a\n    model_path: /models/a\n  - name: a\n    model_path: /models/b\n");
+        assert_eq!(duplicate.models().unwrap_err().to_string(), "Model a is configured more than once");
+
+        let unknown = config("models:\n  - name: a\n    model_path: /models/a\ndefault_model: b\n");
+        assert_eq!(unknown.models().unwrap_err().to_string(), "Default model b is not configured");
+    }
+
+    #[test]
+    fn derives_listeners_from_listen_settings() {
+        let listeners = config("").listeners();
+        assert_eq!(listeners.len(), 1);
+        assert_eq!(listeners[0].address.as_deref(), Some("127.0.0.1:8080"));
+        assert!(!listeners[0].tls);
+        assert_eq!(listeners[0].services, [ListenerService::Api]);
+
+        let listeners = config("tls_cert: /certs/server.pem\nlisten_unix_socket: /run/cylon.sock\n").listeners();
+        assert_eq!(listeners.len(), 2);
+        assert!(listeners[0].tls);
+        assert_eq!(listeners[1].address, None);
+        assert_eq!(listeners[1].unix_socket.as_deref(), Some("/run/cylon.sock"));
+        assert!(!listeners[1].tls);
+        assert_eq!(listeners[1].services, [ListenerService::Api, ListenerService::Admin]);
+    }
+
+    #[test]
+    fn explicit_listeners_replace_listen_settings() {
+        let listeners = config("
+listen_unix_socket: /run/cylon.sock
+listeners:
+  - address: 0.0.0.0:9000
+    services: [api, admin]
+").listeners();
+        assert_eq!(listeners.len(), 1);
+        assert_eq!(listeners[0].address.as_deref(), Some("0.0.0.0:9000"));
+        assert_eq!(listeners[0].services, [ListenerService::Api, ListenerService::Admin]);
+    }
 }
@@ -13,4 +13,19 @@ impl EosTokenHandler {
             EosTokenHandler::None => false,
         }
     }
+
+    /// Also stop on `token_id`
+    pub fn with_token(self, token_id: u32) -> Self {
+        match self {
+            EosTokenHandler::None => EosTokenHandler::Single(token_id),
+            EosTokenHandler::Single(id) if id == token_id => self,
+            EosTokenHandler::Single(id) => EosTokenHandler::Multiple(vec![id, token_id]),
+            EosTokenHandler::Multiple(mut ids) => {
+                if !ids.contains(&token_id) {
+                    ids.push(token_id);
+                }
+                EosTokenHandler::Multiple(ids)
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -1,8 +1,11 @@
 use anyhow::Result;
+use std::time::Duration;
 use candle_core::{DType, Device, Tensor};
 use candle_transformers::generation::{LogitsProcessor, Sampling};
 use crate::EosTokenHandler;
 
+use tracing::info_span;
+
 #[allow(unused_imports)]
 use tracing::{info, debug};
 
@@ -23,7 +26,7 @@ impl InferenceConfig {
             Sampling::ArgMax
         } else {
             let temperature = self.temperature;
-            let sampling = match (self.top_k, self.top_p) {
+            match (self.top_k, self.top_p) {
                 (None, None) => {
                     debug!("Using All sampling with temperature: {}", temperature);
                     Sampling::All { temperature }
@@ -40,13 +43,40 @@ impl InferenceConfig {
                     debug!("Using TopKThenTopP sampling: k={}, p={}, temperature={}", k, p, temperature);
                     Sampling::TopKThenTopP { k, p, temperature }
                 },
-            };
-            sampling
+            }
         };
         LogitsProcessor::from_sampling(self.seed.unwrap_or(42), sampling)
     }
 }
 
+/// Why generation stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinishReason {
+    /// An EOS token was sampled
+    Stop,
+    /// The token limit was reached
+    Length,
+}
+
+impl std::fmt::Display for FinishReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FinishReason::Stop => write!(f, "stop"),
+            FinishReason::Length => write!(f, "length"),
+        }
+    }
+}
+
+/// Measurements collected while generating a single completion
+#[derive(Debug, Clone)]
+pub struct GenerationStats {
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub finish_reason: FinishReason,
+    pub prefill_time: Duration,
+    pub decode_time: Duration,
+}
+
 pub trait ModelInference: Send + Sync {
     type Cache;
     
@@ -66,7 +96,7 @@ impl InferenceEngine {
         mut tokens: Vec<u32>,
         max_tokens: usize,
         config: &InferenceConfig,
-    ) -> Result<Vec<u32>> {
+    ) -> Result<(Vec<u32>, GenerationStats)> {
         let mut cache = model.create_cache(model.use_kv_cache(), model.dtype(), model.device())?;
         let mut logits_processor = config.create_logits_processor();
 
@@ -75,11 +105,16 @@ impl InferenceEngine {
         
         let mut token_generated = 0;
         let mut generated_tokens = Vec::new();
-        let _initial_tokens_len = tokens.len();
+        let initial_tokens_len = tokens.len();
                 
         let prefill_start = std::time::Instant::now();
         let mut generation_start: Option<std::time::Instant> = None;
 
+        // Prefill covers the forward pass over the prompt up to the first sampled token
+        let mut prefill_span = Some(info_span!("prefill", prompt_tokens = initial_tokens_len).entered());
+        let mut decode_span = None;
+        let mut finish_reason = FinishReason::Length;
+
         for index in 0..max_tokens {
             let (context_size, context_index) = if model.use_kv_cache() && index > 0 {
                 (1, tokens.len() - 1)
@@ -133,13 +168,19 @@ impl InferenceEngine {
                 let prefill_time = prefill_start.elapsed();
                 debug!("Prefill completed in {:?} for {} tokens", prefill_time, tokens.len() - 1);
                 generation_start = Some(std::time::Instant::now());
+                prefill_span.take();
+                decode_span = Some(info_span!("decode").entered());
             }
 
             if model.eos_handler().is_eos_token(next_token) {
+                finish_reason = FinishReason::Stop;
                 break;
             }
         }
 
+        prefill_span.take();
+        drop(decode_span);
+
         let total_time = prefill_start.elapsed();
         let generation_time = generation_start.map(|s| s.elapsed()).unwrap_or_default();
         
@@ -152,10 +193,24 @@ impl InferenceEngine {
 
         debug!(
             "{} tokens generated | Total: {:.2} tok/s | Generation only: {:.2} tok/s | Prefill: {:?} | Generation: {:?}",
-            token_generated, total_tokens_per_second, generation_tokens_per_second, 
+            token_generated, total_tokens_per_second, generation_tokens_per_second,
             total_time - generation_time, generation_time
         );
 
-        Ok(generated_tokens)
+        metrics::counter!("cylon_prompt_tokens_total").increment(initial_tokens_len as u64);
+        metrics::counter!("cylon_tokens_generated_total").increment(token_generated as u64);
+        metrics::histogram!("cylon_prefill_duration_seconds").record((total_time - generation_time).as_secs_f64());
+        metrics::histogram!("cylon_decode_duration_seconds").record(generation_time.as_secs_f64());
+        metrics::histogram!("cylon_decode_tokens_per_second").record(generation_tokens_per_second);
+
+        let stats = GenerationStats {
+            prompt_tokens: initial_tokens_len,
+            completion_tokens: token_generated,
+            finish_reason,
+            prefill_time: total_time - generation_time,
+            decode_time: generation_time,
+        };
+
+        Ok((generated_tokens, stats))
     }
 }
\ No newline at end of file
@@ -2,6 +2,6 @@ pub mod inference_engine;
 pub mod eos;
 pub mod textgenerator;
 
-pub use inference_engine::{InferenceEngine, InferenceConfig, ModelInference};
+pub use inference_engine::{InferenceEngine, InferenceConfig, ModelInference, FinishReason, GenerationStats};
 pub use eos::EosTokenHandler;
-pub use textgenerator::TextGenerator;
\ No newline at end of file
+pub use textgenerator::{TextGenerator, Completion, ModelInfo};
\ No newline at end of file
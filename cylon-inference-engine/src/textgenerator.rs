@@ -1,18 +1,48 @@
 use anyhow::Error as E;
 use anyhow::Result;
+use crate::{GenerationStats, InferenceConfig};
+
+/// Generated text along with the measurements taken while producing it
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub text: String,
+    pub stats: GenerationStats,
+}
+
+/// Static properties of a loaded model, reported to clients before they send prompts
+#[derive(Debug, Clone, Default)]
+pub struct ModelInfo {
+    pub family: String,
+    pub context_length: usize,
+    pub dtype: String,
+    pub device: String,
+    pub vocab_size: usize,
+    pub has_chat_template: bool,
+}
 
 pub trait TextGenerator: std::fmt::Debug + Send + Sync {
     fn generate(
         &self,
         prompt: String,
         max_tokens: usize,
-    ) -> Result<String, E>;
+        sampling: &InferenceConfig,
+    ) -> Result<Completion, E>;
     fn inference(
         &self,
-        prompt: &Vec<String>,
+        prompt: &[String],
         max_tokens: usize,
-    ) -> Result<String, E>;
+        sampling: &InferenceConfig,
+    ) -> Result<Completion, E>;
     fn tokenize(&self, text: &str) -> Result<Vec<u32>, E>;
     fn decode(&self, tokens: &[u32]) -> Result<String, E>;
-    fn render(&self, prompt: &Vec<String>) -> Result<String, E>;
-}
\ No newline at end of file
+    fn render(&self, prompt: &[String]) -> Result<String, E>;
+    fn info(&self) -> ModelInfo;
+    /// Default sampling settings, which callers may adjust per request
+    fn inference_config(&self) -> InferenceConfig;
+    fn set_inference_config(&mut self, config: InferenceConfig);
+    /// Whether the chat template accepts `system` messages; when it does not,
+    /// the caller folds them into the first user turn
+    fn supports_system_role(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,402 @@
+use anyhow::{anyhow, bail, Context, Result};
+use minijinja::value::{Kwargs, Value as TemplateValue};
+use minijinja::{context, Environment, Error, ErrorKind};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::ser::{Formatter, PrettyFormatter};
+use serde_json::from_str;
+use std::fmt::{self, Write};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+/// Name the compiled chat template is registered under
+const TEMPLATE_NAME: &str = "chat_template";
+
+/// ChatML framing used by Qwen, for checkpoints whose tokenizer config has no template
+pub const CHATML_TEMPLATE: &str = "{% for message in messages %}<|im_start|>{{ message['role'] }}\n{{ message['content'] }}<|im_end|>\n{% endfor %}{% if add_generation_prompt %}<|im_start|>assistant\n{% endif %}";
+
+/// Chat template and special tokens from a model's `tokenizer_config.json`
+#[derive(Debug, Default, Deserialize)]
+pub struct TokenizerConfig {
+    /// Absent or null for tokenizers without a BOS token, such as Qwen's
+    #[serde(default, deserialize_with = "deserialize_token")]
+    pub bos_token: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_token")]
+    pub eos_token: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_template")]
+    pub chat_template: String,
+    /// `chat_template` compiled once the template is settled
+    #[serde(skip)]
+    pub(crate) environment: Option<Environment<'static>>,
+}
+
+/// A chat template rejected the request's messages with `raise_exception`,
+/// for example on an unsupported role
+#[derive(Debug)]
+pub struct TemplateError(String);
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to render chat template: {}", self.0)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// A special token, written either as its text or as a serialized `AddedToken`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SpecialToken {
+    Text(String),
+    Added { content: String },
+}
+
+/// A chat template, or a list of named templates such as "default" and "tool_use"
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ChatTemplate {
+    Single(String),
+    Named(Vec<NamedTemplate>),
+}
+
+#[derive(Deserialize)]
+struct NamedTemplate {
+    name: String,
+    template: String,
+}
+
+fn deserialize_token<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<String>, D::Error> {
+    let token = Option::<SpecialToken>::deserialize(deserializer)?;
+    Ok(token.map(|token| match token {
+        SpecialToken::Text(text) => text,
+        SpecialToken::Added { content } => content,
+    }))
+}
+
+fn deserialize_template<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<String, D::Error> {
+    let template = match Option::<ChatTemplate>::deserialize(deserializer)? {
+        None => String::new(),
+        Some(ChatTemplate::Single(template)) => template,
+        Some(ChatTemplate::Named(mut templates)) => {
+            // transformers uses the template named "default" unless asked for another
+            match templates.iter().position(|template| template.name == "default") {
+                Some(index) => templates.swap_remove(index).template,
+                None if templates.is_empty() => String::new(),
+                None => {
+                    warn!("No chat template named default, using {}", templates[0].name);
+                    templates.swap_remove(0).template
+                }
+            }
+        }
+    };
+    Ok(template)
+}
+
+impl TokenizerConfig {
+    /// Load `tokenizer_config.json`, taking the chat template from `template_file`
+    /// when set and from `chat_template.jinja` when the config has none
+    pub fn load(model_dir: &Path, template_file: Option<&str>) -> Result<Self> {
+        let path = model_dir.join("tokenizer_config.json");
+        let config = if path.exists() {
+            let file = File::open(&path)
+                .with_context(|| format!("Failed to open tokenizer config at {}", path.display()))?;
+            serde_json::from_reader(&file)
+                .with_context(|| format!("Failed to parse tokenizer config at {}", path.display()))?
+        } else {
+            warn!("No tokenizer config at {}, prompts get no BOS token", path.display());
+            TokenizerConfig::default()
+        };
+
+        config.with_template_file(model_dir, template_file)
+    }
+
+    /// Replace the chat template with the contents of `template_file`, or fill
+    /// a missing one from the `chat_template.jinja` newer checkpoints ship, then
+    /// compile it
+    pub fn with_template_file(mut self, model_dir: &Path, template_file: Option<&str>) -> Result<Self> {
+        let path = match template_file {
+            Some(template_file) => Some(PathBuf::from(template_file)),
+            None => Some(model_dir.join("chat_template.jinja")).filter(|path| !self.has_chat_template() && path.exists()),
+        };
+
+        if let Some(path) = path {
+            self.chat_template = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read chat template at {}", path.display()))?;
+            debug!("Using chat template from {}", path.display());
+        }
+        self.compile()
+    }
+
+    /// Use `template` when the tokenizer config does not provide one
+    pub fn with_default_template(mut self, template: &str) -> Result<Self> {
+        if !self.chat_template.is_empty() {
+            return Ok(self);
+        }
+        self.chat_template = template.to_string();
+        self.compile()
+    }
+
+    /// Compile the chat template with the globals and filters Hugging Face
+    /// templates expect, so syntax errors surface when the model loads
+    fn compile(mut self) -> Result<Self> {
+        self.environment = None;
+        if !self.has_chat_template() {
+            return Ok(self);
+        }
+
+        // transformers renders with trim_blocks, lstrip_blocks and loop controls
+        let mut environment = Environment::new();
+        environment.set_trim_blocks(true);
+        environment.set_lstrip_blocks(true);
+        environment.add_function("raise_exception", raise_exception);
+        environment.add_function("strftime_now", strftime_now);
+        environment.add_filter("tojson", tojson);
+        environment
+            .add_template_owned(TEMPLATE_NAME, self.chat_template.clone())
+            .context("Failed to compile chat template")?;
+
+        self.environment = Some(environment);
+        Ok(self)
+    }
+
+    pub fn has_chat_template(&self) -> bool {
+        !self.chat_template.is_empty()
+    }
+
+    /// Render JSON-encoded messages into a prompt using the chat template
+    pub fn render(&self, prompt: &[String]) -> Result<String> {
+        if !self.has_chat_template() {
+            bail!("Model has no chat template in tokenizer_config.json or chat_template.jinja; set chat_template to a template file");
+        }
+
+        let Some(environment) = &self.environment else {
+            bail!("Chat template was not compiled");
+        };
+
+        // Parsed straight into template values to keep the key order tojson prints
+        let messages = prompt
+            .iter()
+            .map(|message| from_str(message))
+            .collect::<serde_json::Result<Vec<TemplateValue>>>()
+            .context("Failed to parse message")?;
+
+        let template = environment.get_template(TEMPLATE_NAME)?;
+        let rendered = template
+            .render(context! {
+                messages => messages,
+                bos_token => self.bos_token.as_deref().unwrap_or_default(),
+                eos_token => self.eos_token.as_deref().unwrap_or_default(),
+                add_generation_prompt => true,
+                tools => (),
+                documents => (),
+                // Llama 3.1 templates print the date from this, defaulting to the model's release date
+                date_string => chrono::Local::now().format("%d %b %Y").to_string(),
+            })
+            .map_err(|e| match raised_message(&e) {
+                Some(message) => anyhow::Error::new(TemplateError(message)),
+                // Anything else is a broken template rather than a bad request
+                None => anyhow!("Failed to render chat template: {}", e),
+            })?;
+
+        Ok(rendered)
+    }
+}
+
+/// Marks errors a template raised on purpose through `raise_exception`
+#[derive(Debug)]
+struct RaisedException;
+
+impl fmt::Display for RaisedException {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("raised by the chat template")
+    }
+}
+
+impl std::error::Error for RaisedException {}
+
+/// The message of an exception the template raised, if `error` is one
+fn raised_message(error: &Error) -> Option<String> {
+    use std::error::Error as _;
+
+    error
+        .source()
+        .is_some_and(|source| source.is::<RaisedException>())
+        .then(|| error.detail().unwrap_or_default().to_string())
+}
+
+/// `raise_exception(message)`, which templates call to reject a conversation
+fn raise_exception(message: String) -> Result<TemplateValue, Error> {
+    Err(Error::new(ErrorKind::InvalidOperation, message).with_source(RaisedException))
+}
+
+/// `strftime_now(format)`, the current local time formatted like Python's `strftime`
+fn strftime_now(format: String) -> Result<String, Error> {
+    let mut formatted = String::new();
+    write!(formatted, "{}", chrono::Local::now().format(&format))
+        .map_err(|_| Error::new(ErrorKind::InvalidOperation, format!("invalid strftime format {format:?}")))?;
+    Ok(formatted)
+}
+
+/// `tojson(indent=None)`, formatted like Python's `json.dumps` as transformers
+/// does, without the HTML escaping of minijinja's own filter
+fn tojson(value: TemplateValue, kwargs: Kwargs) -> Result<TemplateValue, Error> {
+    let indent: Option<usize> = kwargs.get("indent")?;
+
+    let mut json = Vec::new();
+    let result = match indent {
+        Some(indent) => {
+            let indent = " ".repeat(indent);
+            value.serialize(&mut serde_json::Serializer::with_formatter(&mut json, PrettyFormatter::with_indent(indent.as_bytes())))
+        }
+        None => value.serialize(&mut serde_json::Serializer::with_formatter(&mut json, PythonFormatter)),
+    };
+    result.map_err(|e| Error::new(ErrorKind::InvalidOperation, "cannot serialize to JSON").with_source(e))?;
+
+    let json = String::from_utf8(json).map_err(|e| Error::new(ErrorKind::InvalidOperation, "JSON is not UTF-8").with_source(e))?;
+    Ok(TemplateValue::from_safe_string(json))
+}
+
+/// Single-line JSON with Python's `", "` and `": "` separators
+struct PythonFormatter;
+
+impl Formatter for PythonFormatter {
+    fn begin_array_value<W: ?Sized + io::Write>(&mut self, writer: &mut W, first: bool) -> io::Result<()> {
+        if first { Ok(()) } else { writer.write_all(b", ") }
+    }
+
+    fn begin_object_key<W: ?Sized + io::Write>(&mut self, writer: &mut W, first: bool) -> io::Result<()> {
+        if first { Ok(()) } else { writer.write_all(b", ") }
+    }
+
+    fn begin_object_value<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(b": ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> TokenizerConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn render(template: &str, messages: &[&str]) -> Result<String> {
+        let tokenizer_config = TokenizerConfig {
+            bos_token: Some(String::from("<s>")),
+            eos_token: Some(String::from("</s>")),
+            ..Default::default()
+        };
+        let messages: Vec<String> = messages.iter().map(|message| message.to_string()).collect();
+        tokenizer_config.with_default_template(template)?.render(&messages)
+    }
+
+    #[test]
+    fn reads_special_tokens_as_text_or_added_tokens() {
+        let config = parse(r#"{"bos_token": {"__type": "AddedToken", "content": "<s>", "lstrip": false}, "eos_token": "</s>"}"#);
+        assert_eq!(config.bos_token.as_deref(), Some("<s>"));
+        assert_eq!(config.eos_token.as_deref(), Some("</s>"));
+
+        let config = parse(r#"{"bos_token": null}"#);
+        assert_eq!(config.bos_token, None);
+        assert_eq!(config.eos_token, None);
+        assert!(!config.has_chat_template());
+    }
+
+    #[test]
+    fn picks_the_default_named_template() {
+        let config = parse(r#"{"chat_template": [{"name": "tool_use", "template": "tools"}, {"name": "default", "template": "chat"}]}"#);
+        assert_eq!(config.chat_template, "chat");
+
+        let config = parse(r#"{"chat_template": [{"name": "rag", "template": "documents"}]}"#);
+        assert_eq!(config.chat_template, "documents");
+
+        let config = parse(r#"{"chat_template": []}"#);
+        assert!(!config.has_chat_template());
+
+        let config = parse(r#"{"chat_template": null}"#);
+        assert!(!config.has_chat_template());
+    }
+
+    #[test]
+    fn keeps_the_models_template_over_the_default() {
+        let config = parse(r#"{"chat_template": "{{ bos_token }}"}"#).with_default_template(CHATML_TEMPLATE).unwrap();
+        assert_eq!(config.chat_template, "{{ bos_token }}");
+    }
+
+    #[test]
+    fn renders_with_special_tokens_and_generation_prompt() {
+        let prompt = render(CHATML_TEMPLATE, &[r#"{"role": "user", "content": "hi"}"#]).unwrap();
+        assert_eq!(prompt, "<|im_start|>user\nhi<|im_end|>\n<|im_start|>assistant\n");
+
+        let prompt = render("{{ bos_token }}{% if tools is none %}no tools{% endif %}{{ eos_token }}", &[]).unwrap();
+        assert_eq!(prompt, "<s>no tools</s>");
+    }
+
+    #[test]
+    fn reports_syntax_errors_when_compiling() {
+        let err = TokenizerConfig::default().with_default_template("{% for message in messages %}").unwrap_err();
+        assert_eq!(err.to_string(), "Failed to compile chat template");
+    }
+
+    #[test]
+    fn raise_exception_rejects_the_conversation() {
+        let template = "{% if messages[0].role != 'user' %}{{ raise_exception('Conversation must start with a user message') }}{% endif %}ok";
+        let err = render(template, &[r#"{"role": "assistant", "content": "hi"}"#]).unwrap_err();
+        let err = err.downcast_ref::<TemplateError>().unwrap();
+        assert_eq!(err.to_string(), "Failed to render chat template: Conversation must start with a user message");
+
+        assert_eq!(render(template, &[r#"{"role": "user", "content": "hi"}"#]).unwrap(), "ok");
+    }
+
+    #[test]
+    fn other_render_errors_are_not_template_errors() {
+        let err = render("{{ messages | no_such_filter }}", &[]).unwrap_err();
+        assert!(err.downcast_ref::<TemplateError>().is_none());
+        assert!(err.to_string().starts_with("Failed to render chat template: "));
+    }
+
+    #[test]
+    fn tojson_matches_python_json_dumps() {
+        let message = r#"{"role": "user", "content": "<b>&</b>", "tags": [1, 2]}"#;
+
+        let prompt = render("{{ messages[0] | tojson }}", &[message]).unwrap();
+        assert_eq!(prompt, r#"{"role": "user", "content": "<b>&</b>", "tags": [1, 2]}"#);
+
+        let prompt = render("{{ messages[0] | tojson(indent=2) }}", &[message]).unwrap();
+        assert_eq!(prompt, "{\n  \"role\": \"user\",\n  \"content\": \"<b>&</b>\",\n  \"tags\": [\n    1,\n    2\n  ]\n}");
+    }
+
+    #[test]
+    fn strftime_now_formats_the_current_time() {
+        let before = chrono::Local::now().format("%Y").to_string();
+        let prompt = render(r#"{{ strftime_now("%Y") }}|{{ date_string }}"#, &[]).unwrap();
+        let after = chrono::Local::now().format("%Y").to_string();
+
+        let (year, date) = prompt.split_once('|').unwrap();
+        assert!(year == before || year == after, "{prompt}");
+        assert!(date.ends_with(year), "{prompt}");
+    }
+
+    #[test]
+    fn falls_back_to_chat_template_jinja_unless_overridden() {
+        let dir = std::env::temp_dir().join(format!("cylon-chat-template-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("tokenizer_config.json"), r#"{"bos_token": "<s>"}"#).unwrap();
+        std::fs::write(dir.join("chat_template.jinja"), "{{ bos_token }}jinja").unwrap();
+        let override_file = dir.join("override.jinja");
+        std::fs::write(&override_file, "{{ bos_token }}override").unwrap();
+
+        let config = TokenizerConfig::load(&dir, None).unwrap();
+        let jinja = config.render(&[]).unwrap();
+        let config = TokenizerConfig::load(&dir, override_file.to_str()).unwrap();
+        let overridden = config.render(&[]).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(jinja, "<s>jinja");
+        assert_eq!(overridden, "<s>override");
+    }
+}
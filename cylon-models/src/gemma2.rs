@@ -0,0 +1,100 @@
+use crate::chat_template::TokenizerConfig;
+use crate::generator::{Family, Generator, ModelCache};
+use crate::utils::{
+    device, eos_token_handler, load_safetensor_model_files, model_dir, parse_dtype, read_model_config, use_flash_attn,
+};
+use anyhow::{Context, Error as E, Result};
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::gemma2;
+use tokenizers::Tokenizer;
+use cylon_config::CylonConfig;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+const END_OF_TURN: &str = "<end_of_turn>";
+
+/// Gemma 2, which soft-caps attention and final logits and alternates local and global attention
+///
+/// candle applies the sliding window on every layer during prefill only, so prompts
+/// longer than the window differ slightly from the reference implementation.
+pub type Gemma2Model = Generator<Gemma2>;
+
+#[derive(Debug)]
+pub struct Gemma2 {
+    model: gemma2::Model,
+    config: gemma2::Config,
+}
+
+impl Gemma2Model {
+    pub fn new(config: &CylonConfig) -> Result<Self> {
+        let device = device()?;
+        info!("Using device: {:?}", device);
+        let dtype = parse_dtype(&config.dtype)?;
+        info!("Using dtype: {:?}", dtype);
+
+        let model_dir = &model_dir(&config.model_path)?;
+
+        let safetensors_files = load_safetensor_model_files(model_dir)
+            .with_context(|| format!("Failed to load safetensors files at {}", model_dir.display()))?;
+
+        let raw_config = read_model_config(model_dir)?;
+        let gemma_config: gemma2::Config = serde_json::from_value(raw_config.clone())
+            .context("Failed to parse Gemma 2 model config")?;
+        let mut use_flash_attn = use_flash_attn(&device, config.use_flash_attn);
+        // The flash attention kernel cannot soft-cap attention scores
+        if use_flash_attn && gemma_config.attn_logit_softcapping.is_some() {
+            warn!("Flash attention does not support attention soft-capping, ignoring");
+            use_flash_attn = false;
+        }
+
+        let eos_handler = eos_token_handler(model_dir, &raw_config)?;
+
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&safetensors_files, dtype, &device)? };
+
+        let model = gemma2::Model::new(use_flash_attn, &gemma_config, vb)?;
+        let tokenizer = Tokenizer::from_file(model_dir.join("tokenizer.json")).map_err(E::msg)?;
+
+        // Instruct checkpoints end turns with <end_of_turn>, which older generation configs omit
+        let eos_handler = match tokenizer.token_to_id(END_OF_TURN) {
+            Some(id) => eos_handler.with_token(id),
+            None => eos_handler,
+        };
+        debug!("EOS tokens: {:?}", eos_handler);
+
+        let tokenizer_config = TokenizerConfig::load(model_dir, config.chat_template.as_deref())?;
+
+        let family = Gemma2 { model, config: gemma_config };
+        Generator::from_parts(config, family, tokenizer, tokenizer_config, eos_handler, device, dtype)
+    }
+}
+
+impl Family for Gemma2 {
+    type Cache = ModelCache<gemma2::Model>;
+
+    fn name(&self) -> &str {
+        "gemma2"
+    }
+
+    fn context_length(&self) -> usize {
+        self.config.max_position_embeddings
+    }
+
+    fn vocab_size(&self) -> usize {
+        self.config.vocab_size
+    }
+
+    fn create_cache(&self, enable_kv_cache: bool, _dtype: DType, _device: &Device) -> Result<Self::Cache> {
+        Ok(ModelCache::new(&self.model, enable_kv_cache))
+    }
+
+    fn forward(&self, input: &Tensor, context_index: usize, cache: &mut Self::Cache) -> Result<Tensor> {
+        cache.model().forward(input, context_index)?.squeeze(1).map_err(E::from)
+    }
+
+    /// Gemma's template raises on a system turn
+    fn supports_system_role(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,214 @@
+use crate::chat_template::TokenizerConfig;
+use crate::utils::{device_name, sampling_defaults};
+use cylon_inference_engine::{TextGenerator, Completion, ModelInfo, EosTokenHandler, ModelInference, InferenceEngine, InferenceConfig};
+use anyhow::{bail, Error as E, Result};
+use candle_core::{DType, Device, Tensor};
+use tokenizers::Tokenizer;
+use cylon_config::CylonConfig;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn, info_span};
+
+/// The weights and forward pass of a model family
+///
+/// Everything else a served model needs is shared through [`Generator`].
+pub trait Family: std::fmt::Debug + Send + Sync {
+    type Cache;
+
+    /// Family name reported to clients
+    fn name(&self) -> &str;
+    /// Longest sequence clients should send
+    fn context_length(&self) -> usize;
+    fn vocab_size(&self) -> usize;
+    fn create_cache(&self, enable_kv_cache: bool, dtype: DType, device: &Device) -> Result<Self::Cache>;
+    /// Logits for the last position of `input`
+    fn forward(&self, input: &Tensor, context_index: usize, cache: &mut Self::Cache) -> Result<Tensor>;
+
+    /// Whether the tokenizer adds special tokens such as BOS, for families whose
+    /// chat template does not write them itself
+    fn add_special_tokens(&self) -> bool {
+        false
+    }
+
+    /// Whether the chat template accepts `system` messages
+    fn supports_system_role(&self) -> bool {
+        true
+    }
+}
+
+/// A model family together with its tokenizer, chat template, EOS tokens and sampling settings
+#[derive(Debug)]
+pub struct Generator<F> {
+    family: F,
+    tokenizer: Tokenizer,
+    tokenizer_config: TokenizerConfig,
+    device: Device,
+    /// Activations are computed in this dtype
+    dtype: DType,
+    /// Reported as the model's dtype; the quantization for quantized weights
+    weight_type: String,
+    eos_handler: EosTokenHandler,
+    sampling: InferenceConfig,
+    enable_kv_cache: bool,
+}
+
+impl<F: Family> Generator<F> {
+    /// Wrap a loaded family, taking its sampling defaults from `config` and `generation_config.json`
+    ///
+    /// Fails when there is no chat template, since every request is rendered with it.
+    pub fn from_parts(
+        config: &CylonConfig,
+        family: F,
+        tokenizer: Tokenizer,
+        tokenizer_config: TokenizerConfig,
+        eos_handler: EosTokenHandler,
+        device: Device,
+        dtype: DType,
+    ) -> Result<Self> {
+        if !tokenizer_config.has_chat_template() {
+            bail!("Model has no chat template in tokenizer_config.json or chat_template.jinja; set chat_template to a template file");
+        }
+
+        Ok(Generator {
+            family,
+            tokenizer,
+            tokenizer_config,
+            device,
+            dtype,
+            weight_type: dtype.as_str().to_string(),
+            eos_handler,
+            sampling: sampling_defaults(config)?,
+            enable_kv_cache: config.enable_kv_cache,
+        })
+    }
+
+    /// Report `weight_type` as the dtype, for weights stored in another type than the activations
+    pub fn with_weight_type(mut self, weight_type: String) -> Self {
+        self.weight_type = weight_type;
+        self
+    }
+}
+
+/// KV cache of candle models that keep it inside the model
+///
+/// Each generation runs on a clone of the loaded model, whose cache starts empty.
+/// Without the KV cache the whole context is passed on every step, so each
+/// forward pass starts from a fresh clone.
+pub struct ModelCache<M> {
+    model: M,
+    fresh: Option<M>,
+}
+
+impl<M: Clone> ModelCache<M> {
+    pub fn new(model: &M, enable_kv_cache: bool) -> Self {
+        ModelCache {
+            model: model.clone(),
+            fresh: (!enable_kv_cache).then(|| model.clone()),
+        }
+    }
+
+    /// The model to run the next forward pass on
+    pub fn model(&mut self) -> &mut M {
+        if let Some(fresh) = &self.fresh {
+            self.model = fresh.clone();
+        }
+        &mut self.model
+    }
+}
+
+impl<F: Family> ModelInference for Generator<F> {
+    type Cache = F::Cache;
+
+    fn create_cache(&self, enable_kv_cache: bool, dtype: DType, device: &Device) -> Result<Self::Cache> {
+        self.family.create_cache(enable_kv_cache, dtype, device)
+    }
+
+    fn forward(&self, input: &Tensor, context_index: usize, cache: &mut Self::Cache) -> Result<Tensor> {
+        self.family.forward(input, context_index, cache)
+    }
+
+    fn device(&self) -> &Device {
+        &self.device
+    }
+
+    fn dtype(&self) -> DType {
+        self.dtype
+    }
+
+    fn use_kv_cache(&self) -> bool {
+        self.enable_kv_cache
+    }
+
+    fn eos_handler(&self) -> &EosTokenHandler {
+        &self.eos_handler
+    }
+}
+
+impl<F: Family> TextGenerator for Generator<F> {
+    fn generate(
+        &self,
+        prompt: String,
+        max_tokens: usize,
+        sampling: &InferenceConfig,
+    ) -> Result<Completion, E> {
+        let tokens = info_span!("tokenize").in_scope(|| self.tokenize(prompt.as_str()))?;
+
+        let (generated_tokens, stats) = InferenceEngine::generate(self, tokens, max_tokens, sampling)?;
+        let text = info_span!("detokenize").in_scope(|| self.decode(&generated_tokens))?;
+
+        Ok(Completion { text, stats })
+    }
+
+    fn inference(
+        &self,
+        prompt: &[String],
+        max_tokens: usize,
+        sampling: &InferenceConfig,
+    ) -> Result<Completion, E> {
+        let rendered = info_span!("render_template").in_scope(|| self.render(prompt))?;
+
+        self.generate(rendered, max_tokens, sampling)
+    }
+
+    fn tokenize(&self, text: &str) -> Result<Vec<u32>, E> {
+        let tokens = self
+            .tokenizer
+            .encode(text, self.family.add_special_tokens())
+            .map_err(E::msg)?
+            .get_ids()
+            .to_vec();
+
+        Ok(tokens)
+    }
+
+    fn decode(&self, tokens: &[u32]) -> Result<String, E> {
+        self.tokenizer.decode(tokens, true).map_err(E::msg)
+    }
+
+    fn render(&self, prompt: &[String]) -> Result<String, E> {
+        self.tokenizer_config.render(prompt)
+    }
+
+    fn info(&self) -> ModelInfo {
+        ModelInfo {
+            family: self.family.name().to_string(),
+            context_length: self.family.context_length(),
+            dtype: self.weight_type.clone(),
+            device: device_name(&self.device),
+            vocab_size: self.family.vocab_size(),
+            has_chat_template: self.tokenizer_config.has_chat_template(),
+        }
+    }
+
+    fn inference_config(&self) -> InferenceConfig {
+        self.sampling.clone()
+    }
+
+    fn set_inference_config(&mut self, config: InferenceConfig) {
+        self.sampling = config;
+    }
+
+    fn supports_system_role(&self) -> bool {
+        self.family.supports_system_role()
+    }
+}
@@ -0,0 +1,367 @@
+use crate::chat_template::TokenizerConfig;
+use anyhow::{bail, Context, Error as E, Result};
+use candle_core::quantized::gguf_file::{Content, Value};
+use cylon_inference_engine::EosTokenHandler;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tokenizers::Tokenizer;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+/// Pre-tokenizer regex of the Llama 3 BPE tokenizer (`tokenizer.ggml.pre = "llama-bpe"`)
+const LLAMA3_SPLIT_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+";
+
+/// GGUF token types, from `tokenizer.ggml.token_type`
+const TOKEN_TYPE_NORMAL: i32 = 1;
+const TOKEN_TYPE_CONTROL: i32 = 3;
+const TOKEN_TYPE_USER_DEFINED: i32 = 4;
+
+/// The GGUF file a model path refers to: the path itself, or the only `.gguf` file in a directory
+pub fn find_gguf_file(model_path: &Path) -> Result<Option<PathBuf>> {
+    if model_path.is_file() {
+        return Ok(is_gguf(model_path).then(|| model_path.to_path_buf()));
+    }
+    if !model_path.is_dir() {
+        return Ok(None);
+    }
+
+    let mut files = std::fs::read_dir(model_path)
+        .with_context(|| format!("Failed to read model directory {}", model_path.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    files.retain(|path| is_gguf(path));
+
+    match files.len() {
+        0 => Ok(None),
+        1 => Ok(files.pop()),
+        _ => bail!("Found several GGUF files in {}; set model_path to one of them", model_path.display()),
+    }
+}
+
+fn is_gguf(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gguf"))
+}
+
+/// Read the metadata and tensor index of a GGUF file; tensors are read separately
+pub fn read_gguf(path: &Path) -> Result<(Content, File)> {
+    let mut file = File::open(path)
+        .with_context(|| format!("Failed to open GGUF file at {}", path.display()))?;
+    let content = Content::read(&mut file)
+        .with_context(|| format!("Failed to read GGUF file at {}", path.display()))?;
+    Ok((content, file))
+}
+
+/// Typed access to GGUF metadata
+pub struct Metadata<'a>(pub &'a HashMap<String, Value>);
+
+impl Metadata<'_> {
+    pub fn get(&self, key: &str) -> Result<&Value> {
+        self.0.get(key).with_context(|| format!("GGUF metadata has no {key}"))
+    }
+
+    pub fn u32(&self, key: &str) -> Result<u32> {
+        self.get(key)?.to_u32().with_context(|| format!("GGUF metadata {key} is not a u32"))
+    }
+
+    pub fn opt_u32(&self, key: &str) -> Option<u32> {
+        self.0.get(key).and_then(|value| value.to_u32().ok())
+    }
+
+    pub fn opt_string(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_string().ok()).map(String::as_str)
+    }
+
+    fn strings(&self, key: &str) -> Result<Vec<&str>> {
+        self.get(key)?
+            .to_vec()?
+            .iter()
+            .map(|value| value.to_string().map(String::as_str).map_err(E::from))
+            .collect::<Result<_>>()
+            .with_context(|| format!("GGUF metadata {key} is not a list of strings"))
+    }
+
+    /// A numeric list, or None when absent
+    fn numbers<T>(&self, key: &str, convert: impl Fn(&Value) -> candle_core::Result<T>) -> Result<Option<Vec<T>>> {
+        let Some(value) = self.0.get(key) else {
+            return Ok(None);
+        };
+        let values = value
+            .to_vec()?
+            .iter()
+            .map(convert)
+            .collect::<candle_core::Result<_>>()
+            .with_context(|| format!("GGUF metadata {key} has an unexpected type"))?;
+        Ok(Some(values))
+    }
+
+    pub fn tokens(&self) -> Result<Vec<&str>> {
+        self.strings("tokenizer.ggml.tokens")
+    }
+
+    fn token(&self, key: &str) -> Result<Option<String>> {
+        let Some(id) = self.opt_u32(key) else {
+            return Ok(None);
+        };
+        let tokens = self.tokens()?;
+        let token = tokens
+            .get(id as usize)
+            .with_context(|| format!("GGUF metadata {key} is out of range"))?;
+        Ok(Some(token.to_string()))
+    }
+
+    /// EOS tokens, including the end-of-turn token chat models stop on
+    pub fn eos_token_handler(&self) -> EosTokenHandler {
+        let eos = match self.opt_u32("tokenizer.ggml.eos_token_id") {
+            Some(id) => EosTokenHandler::Single(id),
+            None => EosTokenHandler::None,
+        };
+        match self.opt_u32("tokenizer.ggml.eot_token_id") {
+            Some(id) => eos.with_token(id),
+            None => eos,
+        }
+    }
+}
+
+/// The tokenizer, from `tokenizer.json` next to the GGUF file or else from the GGUF metadata
+pub fn load_tokenizer(model_dir: &Path, metadata: &Metadata) -> Result<Tokenizer> {
+    let path = model_dir.join("tokenizer.json");
+    if path.exists() {
+        return Tokenizer::from_file(&path).map_err(E::msg);
+    }
+
+    info!("No tokenizer.json in {}, building the tokenizer from GGUF metadata", model_dir.display());
+    let spec = match metadata.opt_string("tokenizer.ggml.model") {
+        Some("llama") => sentencepiece_tokenizer(metadata)?,
+        Some("gpt2") => byte_level_tokenizer(metadata)?,
+        Some(model) => bail!("Unsupported GGUF tokenizer model {model}"),
+        None => bail!("GGUF metadata has no tokenizer and {} does not exist", path.display()),
+    };
+    Tokenizer::from_bytes(serde_json::to_vec(&spec)?).map_err(E::msg)
+}
+
+/// The chat template, from `template_file` when set, from `tokenizer_config.json`
+/// next to the GGUF file, or else from the GGUF metadata
+pub fn load_tokenizer_config(model_dir: &Path, metadata: &Metadata, template_file: Option<&str>) -> Result<TokenizerConfig> {
+    if model_dir.join("tokenizer_config.json").exists() {
+        return TokenizerConfig::load(model_dir, template_file);
+    }
+
+    let tokenizer_config = TokenizerConfig {
+        bos_token: metadata.token("tokenizer.ggml.bos_token_id")?,
+        eos_token: metadata.token("tokenizer.ggml.eos_token_id")?,
+        chat_template: metadata.opt_string("tokenizer.chat_template").unwrap_or_default().to_string(),
+        ..Default::default()
+    };
+    tokenizer_config.with_template_file(model_dir, template_file)
+}
+
+/// Control and user-defined tokens, matched before the model splits the text
+fn added_tokens(tokens: &[&str], token_types: &Option<Vec<i32>>) -> Vec<serde_json::Value> {
+    let Some(token_types) = token_types else {
+        return Vec::new();
+    };
+    tokens
+        .iter()
+        .zip(token_types)
+        .enumerate()
+        .filter(|(_, (_, token_type))| matches!(**token_type, TOKEN_TYPE_CONTROL | TOKEN_TYPE_USER_DEFINED))
+        .map(|(id, (token, token_type))| json!({
+            "id": id,
+            "content": token,
+            "single_word": false,
+            "lstrip": false,
+            "rstrip": false,
+            "normalized": false,
+            "special": *token_type == TOKEN_TYPE_CONTROL,
+        }))
+        .collect()
+}
+
+/// SentencePiece BPE, as used by Llama 2 and Mistral
+///
+/// GGUF stores piece scores instead of merges, so the merges are recovered by
+/// ranking every split of a piece into two known pieces by the piece's score.
+fn sentencepiece_tokenizer(metadata: &Metadata) -> Result<serde_json::Value> {
+    let tokens = metadata.tokens()?;
+    let scores = metadata.numbers("tokenizer.ggml.scores", Value::to_f32)?;
+    let token_types = metadata.numbers("tokenizer.ggml.token_type", Value::to_i32)?;
+
+    let vocab: HashMap<&str, usize> = tokens.iter().enumerate().map(|(id, token)| (*token, id)).collect();
+    let mut merges = Vec::new();
+    for (id, token) in tokens.iter().enumerate() {
+        if token_types.as_ref().is_some_and(|types| types[id] != TOKEN_TYPE_NORMAL) {
+            continue;
+        }
+        let score = scores.as_ref().map_or(-(id as f32), |scores| scores[id]);
+        for (split, _) in token.char_indices().skip(1) {
+            let (left, right) = token.split_at(split);
+            if let (Some(&left_id), Some(&right_id)) = (vocab.get(left), vocab.get(right)) {
+                merges.push((score, id, left_id, right_id, left, right));
+            }
+        }
+    }
+    merges.sort_by(|a, b| b.0.total_cmp(&a.0).then((a.1, a.2, a.3).cmp(&(b.1, b.2, b.3))));
+    let merges: Vec<_> = merges.into_iter().map(|(.., left, right)| [left, right]).collect();
+
+    let unk_token = metadata.token("tokenizer.ggml.unknown_token_id")?;
+
+    Ok(json!({
+        "version": "1.0",
+        "added_tokens": added_tokens(&tokens, &token_types),
+        "normalizer": null,
+        "pre_tokenizer": {"type": "Metaspace", "replacement": "▁", "prepend_scheme": "first", "split": false},
+        "post_processor": null,
+        "decoder": {"type": "Sequence", "decoders": [
+            {"type": "Replace", "pattern": {"String": "▁"}, "content": " "},
+            {"type": "ByteFallback"},
+            {"type": "Fuse"},
+            {"type": "Strip", "content": " ", "start": 1, "stop": 0},
+        ]},
+        "model": {
+            "type": "BPE",
+            "unk_token": unk_token,
+            "fuse_unk": true,
+            "byte_fallback": true,
+            "vocab": vocab,
+            "merges": merges,
+        },
+    }))
+}
+
+/// Byte-level BPE, as used by Llama 3
+fn byte_level_tokenizer(metadata: &Metadata) -> Result<serde_json::Value> {
+    let tokens = metadata.tokens()?;
+    let token_types = metadata.numbers("tokenizer.ggml.token_type", Value::to_i32)?;
+    let merges = metadata.strings("tokenizer.ggml.merges")?;
+
+    let vocab: HashMap<&str, usize> = tokens.iter().enumerate().map(|(id, token)| (*token, id)).collect();
+    let merges = merges
+        .iter()
+        .map(|merge| merge.split_once(' ').with_context(|| format!("Malformed GGUF merge {merge:?}")))
+        .collect::<Result<Vec<_>>>()?;
+
+    let byte_level = json!({"type": "ByteLevel", "add_prefix_space": false, "trim_offsets": true, "use_regex": false});
+    let pre_tokenizer = match metadata.opt_string("tokenizer.ggml.pre") {
+        Some("llama-bpe") => json!({"type": "Sequence", "pretokenizers": [
+            {"type": "Split", "pattern": {"Regex": LLAMA3_SPLIT_PATTERN}, "behavior": "Isolated", "invert": false},
+            byte_level,
+        ]}),
+        pre => {
+            if let Some(pre) = pre.filter(|pre| *pre != "default" && *pre != "gpt2") {
+                warn!("Unknown GGUF pre-tokenizer {pre}, splitting like GPT-2");
+            }
+            json!({"type": "ByteLevel", "add_prefix_space": false, "trim_offsets": true, "use_regex": true})
+        }
+    };
+
+    Ok(json!({
+        "version": "1.0",
+        "added_tokens": added_tokens(&tokens, &token_types),
+        "normalizer": null,
+        "pre_tokenizer": pre_tokenizer,
+        "post_processor": null,
+        "decoder": {"type": "ByteLevel", "add_prefix_space": true, "trim_offsets": true, "use_regex": true},
+        "model": {
+            "type": "BPE",
+            "fuse_unk": false,
+            "byte_fallback": false,
+            "ignore_merges": true,
+            "vocab": vocab,
+            "merges": merges,
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Value {
+        Value::Array(values.iter().map(|value| Value::String(value.to_string())).collect())
+    }
+
+    fn token_types(types: &[i32]) -> Value {
+        Value::Array(types.iter().copied().map(Value::I32).collect())
+    }
+
+    /// A directory without tokenizer.json or tokenizer_config.json
+    fn empty_dir() -> PathBuf {
+        std::env::temp_dir().join("cylon-gguf-tests-no-such-dir")
+    }
+
+    fn sentencepiece_metadata() -> HashMap<String, Value> {
+        HashMap::from([
+            (String::from("tokenizer.ggml.model"), Value::String(String::from("llama"))),
+            (String::from("tokenizer.ggml.tokens"), strings(&["<unk>", "<s>", "</s>", "▁", "h", "i", "▁h", "▁hi"])),
+            (String::from("tokenizer.ggml.scores"), Value::Array([0.0, 0.0, 0.0, -1.0, -2.0, -3.0, -4.0, -5.0].map(Value::F32).to_vec())),
+            (String::from("tokenizer.ggml.token_type"), token_types(&[2, 3, 3, 1, 1, 1, 1, 1])),
+            (String::from("tokenizer.ggml.unknown_token_id"), Value::U32(0)),
+            (String::from("tokenizer.ggml.bos_token_id"), Value::U32(1)),
+            (String::from("tokenizer.ggml.eos_token_id"), Value::U32(2)),
+        ])
+    }
+
+    fn byte_level_metadata() -> HashMap<String, Value> {
+        HashMap::from([
+            (String::from("tokenizer.ggml.model"), Value::String(String::from("gpt2"))),
+            (String::from("tokenizer.ggml.pre"), Value::String(String::from("llama-bpe"))),
+            (String::from("tokenizer.ggml.tokens"), strings(&["h", "i", "hi", "Ġ", "Ġhi", "<|eot_id|>"])),
+            (String::from("tokenizer.ggml.merges"), strings(&["h i", "Ġ hi"])),
+            (String::from("tokenizer.ggml.token_type"), token_types(&[1, 1, 1, 1, 1, 3])),
+            (String::from("tokenizer.ggml.eos_token_id"), Value::U32(2)),
+            (String::from("tokenizer.ggml.eot_token_id"), Value::U32(5)),
+        ])
+    }
+
+    #[test]
+    fn rebuilds_sentencepiece_tokenizers_from_scores() {
+        let metadata = sentencepiece_metadata();
+        let tokenizer = load_tokenizer(&empty_dir(), &Metadata(&metadata)).unwrap();
+
+        let encoding = tokenizer.encode("hi hi", false).unwrap();
+        assert_eq!(encoding.get_ids(), [7, 7]);
+        assert_eq!(tokenizer.decode(&[7, 7], false).unwrap(), "hi hi");
+        assert_eq!(tokenizer.token_to_id("<s>"), Some(1));
+    }
+
+    #[test]
+    fn rebuilds_byte_level_tokenizers_from_merges() {
+        let metadata = byte_level_metadata();
+        let tokenizer = load_tokenizer(&empty_dir(), &Metadata(&metadata)).unwrap();
+
+        let encoding = tokenizer.encode("hi hi<|eot_id|>", false).unwrap();
+        assert_eq!(encoding.get_ids(), [2, 4, 5]);
+        assert_eq!(tokenizer.decode(&[2, 4], false).unwrap(), "hi hi");
+    }
+
+    #[test]
+    fn rejects_unknown_tokenizer_models() {
+        let mut metadata = sentencepiece_metadata();
+        metadata.insert(String::from("tokenizer.ggml.model"), Value::String(String::from("bert")));
+        let err = load_tokenizer(&empty_dir(), &Metadata(&metadata)).unwrap_err();
+        assert_eq!(err.to_string(), "Unsupported GGUF tokenizer model bert");
+    }
+
+    #[test]
+    fn reads_special_tokens_and_template_from_metadata() {
+        let mut metadata = sentencepiece_metadata();
+        metadata.insert(String::from("tokenizer.chat_template"), Value::String(String::from("{{ bos_token }}")));
+        let tokenizer_config = load_tokenizer_config(&empty_dir(), &Metadata(&metadata), None).unwrap();
+
+        assert_eq!(tokenizer_config.bos_token.as_deref(), Some("<s>"));
+        assert_eq!(tokenizer_config.eos_token.as_deref(), Some("</s>"));
+        assert_eq!(tokenizer_config.chat_template, "{{ bos_token }}");
+    }
+
+    #[test]
+    fn stops_on_eos_and_end_of_turn_tokens() {
+        let metadata = byte_level_metadata();
+        let eos = Metadata(&metadata).eos_token_handler();
+        assert!(eos.is_eos_token(2));
+        assert!(eos.is_eos_token(5));
+        assert!(!eos.is_eos_token(4));
+    }
+}
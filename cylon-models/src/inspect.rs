@@ -0,0 +1,428 @@
+use crate::chat_template::{TokenizerConfig, CHATML_TEMPLATE};
+use crate::gguf::{find_gguf_file, load_tokenizer, load_tokenizer_config, read_gguf, Metadata};
+use crate::quantize::{quantization_name, quantized_tensor_size};
+use crate::{supported_families, unsupported_family};
+use crate::utils::{eos_token_handler, load_safetensor_model_files, read_model_config, resolve_model_path};
+use anyhow::{Error as E, Result};
+use candle_core::quantized::gguf_file::Value as GgufValue;
+use candle_core::quantized::GgmlDType;
+use candle_core::safetensors::MmapedSafetensors;
+use cylon_inference_engine::EosTokenHandler;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tokenizers::Tokenizer;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+const MB: u64 = 1024 * 1024;
+
+/// `config.json` fields worth reporting, in display order
+const CONFIG_KEYS: &[&str] = &[
+    "architectures",
+    "hidden_size",
+    "intermediate_size",
+    "num_hidden_layers",
+    "num_attention_heads",
+    "num_key_value_heads",
+    "head_dim",
+    "vocab_size",
+    "max_position_embeddings",
+    "rope_theta",
+    "rope_scaling",
+    "sliding_window",
+    "tie_word_embeddings",
+    "torch_dtype",
+];
+
+/// GGUF metadata fields worth reporting, after the `<architecture>.` prefix
+const GGUF_KEYS: &[&str] = &[
+    "context_length",
+    "embedding_length",
+    "feed_forward_length",
+    "block_count",
+    "attention.head_count",
+    "attention.head_count_kv",
+    "rope.freq_base",
+];
+
+/// Families whose safetensors weights can be quantized while loading
+const QUANTIZABLE_FAMILIES: &[&str] = &["llama", "mistral"];
+
+/// Weight types reported for the `quantize` option
+const QUANTIZATIONS: &[GgmlDType] = &[GgmlDType::Q8_0, GgmlDType::Q4K];
+
+/// What Cylon would make of a model, gathered without loading its weights
+#[derive(Debug, Default)]
+pub struct Inspection {
+    pub model_path: PathBuf,
+    pub format: String,
+    pub family: Option<String>,
+    pub supported: bool,
+    pub architecture: Vec<(String, String)>,
+    pub tensor_count: usize,
+    pub parameter_count: u64,
+    /// Bytes of the weights as stored, with the type they are stored as
+    pub stored_size: Option<(String, u64)>,
+    /// Estimated bytes of the weights once loaded, per dtype
+    pub memory: Vec<(String, u64)>,
+    pub vocab_size: Option<usize>,
+    pub eos_tokens: Vec<String>,
+    /// The sample conversation rendered with the chat template
+    pub rendered_sample: Option<String>,
+    /// Anything that would stop the model from loading or serving chat requests
+    pub problems: Vec<String>,
+}
+
+impl Inspection {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Inspect the model at `model_path`, detecting its family unless `family` is given
+/// and rendering with `template_file` instead of its own chat template when set
+///
+/// Reads configs, tokenizer files and tensor headers only, so even large
+/// checkpoints inspect in about a second. Problems are collected rather than
+/// returned so one report lists all of them; only an unresolvable path fails.
+pub fn inspect(model_path: &str, family: Option<&str>, template_file: Option<&str>) -> Result<Inspection> {
+    let model_path = resolve_model_path(model_path)?;
+    let mut inspection = Inspection { model_path: model_path.clone(), ..Default::default() };
+
+    match find_gguf_file(&model_path) {
+        Ok(Some(gguf_path)) => inspect_gguf(&gguf_path, family, template_file, &mut inspection),
+        Ok(None) => inspect_safetensors(&model_path, family, template_file, &mut inspection),
+        Err(e) => inspection.problems.push(format!("{e:#}")),
+    }
+
+    Ok(inspection)
+}
+
+fn inspect_safetensors(model_dir: &Path, family: Option<&str>, template_file: Option<&str>, inspection: &mut Inspection) {
+    inspection.format = String::from("safetensors");
+
+    let config = match read_model_config(model_dir) {
+        Ok(config) => config,
+        Err(e) => {
+            inspection.problems.push(format!("{e:#}"));
+            Value::Null
+        }
+    };
+
+    let family = family.map(str::to_string).or_else(|| config.get("model_type").and_then(Value::as_str).map(str::to_string));
+    check_family(family.as_deref(), inspection);
+
+    inspection.architecture = CONFIG_KEYS
+        .iter()
+        .filter_map(|key| config.get(*key).filter(|value| !value.is_null()).map(|value| (key.to_string(), value.to_string())))
+        .collect();
+
+    match load_safetensor_model_files(model_dir) {
+        Ok(files) => {
+            let quantizable = family.as_deref().is_some_and(|family| QUANTIZABLE_FAMILIES.contains(&family));
+            if let Err(e) = inspect_tensors(&files, quantizable, inspection) {
+                inspection.problems.push(format!("{e:#}"));
+            }
+        }
+        Err(e) => inspection.problems.push(format!("{e:#}")),
+    }
+
+    let tokenizer = match Tokenizer::from_file(model_dir.join("tokenizer.json")).map_err(E::msg) {
+        Ok(tokenizer) => Some(tokenizer),
+        Err(e) => {
+            inspection.problems.push(format!("Failed to load tokenizer at {}: {e:#}", model_dir.join("tokenizer.json").display()));
+            None
+        }
+    };
+    inspection.vocab_size = tokenizer.as_ref().map(|tokenizer| tokenizer.get_vocab_size(true));
+
+    if let (Some(vocab_size), Some(embeddings)) = (inspection.vocab_size, config.get("vocab_size").and_then(Value::as_u64))
+        && vocab_size as u64 > embeddings
+    {
+        inspection.problems.push(format!("Tokenizer has {vocab_size} tokens but the model only embeds {embeddings}"));
+    }
+
+    let eos_handler = match eos_token_handler(model_dir, &config) {
+        Ok(eos_handler) => eos_handler,
+        Err(e) => {
+            inspection.problems.push(format!("{e:#}"));
+            EosTokenHandler::None
+        }
+    };
+    // Gemma stops on its end-of-turn token as well as on EOS
+    let eos_handler = match (family.as_deref(), &tokenizer) {
+        (Some("gemma" | "gemma2"), Some(tokenizer)) => match tokenizer.token_to_id("<end_of_turn>") {
+            Some(id) => eos_handler.with_token(id),
+            None => eos_handler,
+        },
+        _ => eos_handler,
+    };
+    inspection.eos_tokens = eos_tokens(&eos_handler, tokenizer.as_ref(), inspection);
+
+    let tokenizer_config = TokenizerConfig::load(model_dir, template_file).and_then(|tokenizer_config| match family.as_deref() {
+        Some("qwen2") => tokenizer_config.with_default_template(CHATML_TEMPLATE),
+        _ => Ok(tokenizer_config),
+    });
+    match tokenizer_config {
+        Ok(tokenizer_config) => render_sample(&tokenizer_config, family.as_deref(), inspection),
+        Err(e) => inspection.problems.push(format!("{e:#}")),
+    }
+}
+
+fn inspect_gguf(gguf_path: &Path, family: Option<&str>, template_file: Option<&str>, inspection: &mut Inspection) {
+    inspection.format = String::from("gguf");
+    inspection.model_path = gguf_path.to_path_buf();
+
+    let (content, _) = match read_gguf(gguf_path) {
+        Ok(gguf) => gguf,
+        Err(e) => {
+            inspection.problems.push(format!("{e:#}"));
+            return;
+        }
+    };
+    let metadata = Metadata(&content.metadata);
+
+    let architecture = metadata.opt_string("general.architecture");
+    let family = family.or(architecture);
+    check_family(family, inspection);
+    if family.is_some_and(|family| family != "llama") {
+        inspection.problems.push(String::from("GGUF files are only supported for the llama family"));
+    }
+
+    if let Some(architecture) = architecture {
+        inspection.architecture = GGUF_KEYS
+            .iter()
+            .filter_map(|key| {
+                let value = content.metadata.get(&format!("{architecture}.{key}"))?;
+                Some((key.to_string(), gguf_value_string(value)))
+            })
+            .collect();
+    }
+
+    inspection.tensor_count = content.tensor_infos.len();
+    let mut stored_size = 0;
+    let mut stored_types = BTreeMap::new();
+    for info in content.tensor_infos.values() {
+        let elements = info.shape.elem_count();
+        inspection.parameter_count += elements as u64;
+        stored_size += (elements / info.ggml_dtype.block_size() * info.ggml_dtype.type_size()) as u64;
+        *stored_types.entry(quantization_name(info.ggml_dtype)).or_insert(0) += 1;
+    }
+    let stored_type = stored_types.into_iter().max_by_key(|(_, count)| *count).map(|(name, _)| name).unwrap_or_default();
+    // GGUF weights stay in their stored quantization
+    inspection.memory = vec![(stored_type.clone(), stored_size)];
+    inspection.stored_size = Some((stored_type, stored_size));
+
+    let model_dir = gguf_path.parent().unwrap_or(Path::new("."));
+    let tokenizer = match load_tokenizer(model_dir, &metadata) {
+        Ok(tokenizer) => Some(tokenizer),
+        Err(e) => {
+            inspection.problems.push(format!("Failed to load tokenizer: {e:#}"));
+            None
+        }
+    };
+    inspection.vocab_size = tokenizer.as_ref().map(|tokenizer| tokenizer.get_vocab_size(true));
+    inspection.eos_tokens = eos_tokens(&metadata.eos_token_handler(), tokenizer.as_ref(), inspection);
+
+    match load_tokenizer_config(model_dir, &metadata, template_file) {
+        Ok(tokenizer_config) => render_sample(&tokenizer_config, family, inspection),
+        Err(e) => inspection.problems.push(format!("{e:#}")),
+    }
+}
+
+fn check_family(family: Option<&str>, inspection: &mut Inspection) {
+    inspection.family = family.map(str::to_string);
+    match family {
+        Some(family) => {
+            inspection.supported = supported_families().contains(&family);
+            if !inspection.supported {
+                inspection.problems.push(unsupported_family(family));
+            }
+        }
+        None => inspection.problems.push(String::from("Cannot detect the model family; pass --model-family")),
+    }
+}
+
+/// Tensor and parameter counts from the safetensors headers, and the memory each dtype would take
+fn inspect_tensors(files: &[PathBuf], quantizable: bool, inspection: &mut Inspection) -> Result<()> {
+    let safetensors = unsafe { MmapedSafetensors::multi(files)? };
+    let tensors = safetensors.tensors();
+
+    let mut elements = 0;
+    let mut stored_size = 0;
+    let mut stored_types = BTreeMap::new();
+    let mut quantized_sizes = vec![0; QUANTIZATIONS.len()];
+    for (_, view) in &tensors {
+        elements += view.shape().iter().product::<usize>() as u64;
+        stored_size += view.data().len() as u64;
+        *stored_types.entry(format!("{:?}", view.dtype()).to_lowercase()).or_insert(0) += 1;
+        for (size, dtype) in quantized_sizes.iter_mut().zip(QUANTIZATIONS) {
+            *size += quantized_tensor_size(view.shape(), *dtype);
+        }
+    }
+
+    inspection.tensor_count = tensors.len();
+    inspection.parameter_count = elements;
+    let stored_type = stored_types.into_iter().max_by_key(|(_, count)| *count).map(|(name, _)| name).unwrap_or_default();
+    inspection.stored_size = Some((stored_type, stored_size));
+
+    inspection.memory = vec![
+        (String::from("f32"), elements * 4),
+        (String::from("f16"), elements * 2),
+        (String::from("bf16"), elements * 2),
+    ];
+    if quantizable {
+        for (dtype, size) in QUANTIZATIONS.iter().zip(quantized_sizes) {
+            inspection.memory.push((format!("quantize {}", quantization_name(*dtype)), size));
+        }
+    }
+    Ok(())
+}
+
+/// EOS token ids with their text, flagging ids the tokenizer does not know
+fn eos_tokens(eos_handler: &EosTokenHandler, tokenizer: Option<&Tokenizer>, inspection: &mut Inspection) -> Vec<String> {
+    let ids = match eos_handler {
+        EosTokenHandler::Single(id) => vec![*id],
+        EosTokenHandler::Multiple(ids) => ids.clone(),
+        EosTokenHandler::None => Vec::new(),
+    };
+
+    ids.into_iter()
+        .map(|id| match tokenizer.map(|tokenizer| tokenizer.id_to_token(id)) {
+            Some(Some(token)) => format!("{id} {token:?}"),
+            Some(None) => {
+                inspection.problems.push(format!("EOS token {id} is not in the tokenizer vocabulary"));
+                format!("{id} (unknown)")
+            }
+            None => id.to_string(),
+        })
+        .collect()
+}
+
+/// A message of the sample conversation, serialized in the same field order as the server's
+#[derive(Serialize)]
+struct SampleMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+/// Render a short conversation the way the server would, with a system message,
+/// a finished exchange and a pending user turn
+fn render_sample(tokenizer_config: &TokenizerConfig, family: Option<&str>, inspection: &mut Inspection) {
+    if !tokenizer_config.has_chat_template() {
+        inspection.problems.push(String::from("Model has no chat template in tokenizer_config.json or chat_template.jinja"));
+        return;
+    }
+
+    // Gemma rejects system messages, so the server folds them into the first user turn
+    let folds_system = matches!(family, Some("gemma" | "gemma2"));
+    let system = "You are a helpful assistant.";
+    let question = "What is the capital of France?";
+    let first_user = match folds_system {
+        true => format!("{system}\n\n{question}"),
+        false => question.to_string(),
+    };
+
+    let mut messages = Vec::new();
+    if !folds_system {
+        messages.push(SampleMessage { role: "system", content: system });
+    }
+    messages.push(SampleMessage { role: "user", content: &first_user });
+    messages.push(SampleMessage { role: "assistant", content: "The capital of France is Paris." });
+    messages.push(SampleMessage { role: "user", content: "And of Italy?" });
+    let prompt: Vec<String> = messages.iter().filter_map(|message| serde_json::to_string(message).ok()).collect();
+
+    match tokenizer_config.render(&prompt) {
+        Ok(rendered) => inspection.rendered_sample = Some(rendered),
+        Err(e) => inspection.problems.push(format!("Sample conversation: {e:#}")),
+    }
+}
+
+fn gguf_value_string(value: &GgufValue) -> String {
+    match value {
+        GgufValue::U8(v) => v.to_string(),
+        GgufValue::I8(v) => v.to_string(),
+        GgufValue::U16(v) => v.to_string(),
+        GgufValue::I16(v) => v.to_string(),
+        GgufValue::U32(v) => v.to_string(),
+        GgufValue::I32(v) => v.to_string(),
+        GgufValue::U64(v) => v.to_string(),
+        GgufValue::I64(v) => v.to_string(),
+        GgufValue::F32(v) => v.to_string(),
+        GgufValue::F64(v) => v.to_string(),
+        GgufValue::Bool(v) => v.to_string(),
+        GgufValue::String(v) => v.clone(),
+        GgufValue::Array(values) => format!("[{} values]", values.len()),
+    }
+}
+
+impl fmt::Display for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Model:          {}", self.model_path.display())?;
+        writeln!(f, "Format:         {}", self.format)?;
+        match &self.family {
+            Some(family) if self.supported => writeln!(f, "Family:         {family}")?,
+            Some(family) => writeln!(f, "Family:         {family} (not supported by this build)")?,
+            None => writeln!(f, "Family:         unknown")?,
+        }
+
+        if !self.architecture.is_empty() {
+            writeln!(f, "Architecture:")?;
+            for (key, value) in &self.architecture {
+                writeln!(f, "  {key:<26}{value}")?;
+            }
+        }
+
+        if let Some((stored_type, stored_size)) = &self.stored_size {
+            writeln!(
+                f,
+                "Tensors:        {} tensors, {} parameters, {} MB as {}",
+                self.tensor_count,
+                self.parameter_count,
+                stored_size.div_ceil(MB),
+                stored_type
+            )?;
+        }
+        if !self.memory.is_empty() {
+            writeln!(f, "Memory:")?;
+            for (dtype, size) in &self.memory {
+                writeln!(f, "  {:<26}{} MB", dtype, size.div_ceil(MB))?;
+            }
+        }
+
+        match self.vocab_size {
+            Some(vocab_size) => writeln!(f, "Vocabulary:     {vocab_size} tokens")?,
+            None => writeln!(f, "Vocabulary:     unknown")?,
+        }
+        match self.eos_tokens.is_empty() {
+            true => writeln!(f, "EOS tokens:     none; generation stops at max_tokens")?,
+            false => writeln!(f, "EOS tokens:     {}", self.eos_tokens.join(", "))?,
+        }
+
+        match &self.rendered_sample {
+            Some(rendered) => {
+                writeln!(f, "Chat template:  renders a sample conversation")?;
+                for line in rendered.lines() {
+                    writeln!(f, "  | {line}")?;
+                }
+            }
+            None => writeln!(f, "Chat template:  does not render")?,
+        }
+
+        match self.problems.is_empty() {
+            true => writeln!(f, "No problems found"),
+            false => {
+                writeln!(f, "Problems:")?;
+                for problem in &self.problems {
+                    writeln!(f, "  - {problem}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
@@ -1,4 +1,9 @@
 pub mod utils;
+pub mod chat_template;
+pub mod generator;
+pub mod gguf;
+pub mod quantize;
+pub mod inspect;
 
 #[cfg(feature = "llama")]
 pub mod llama;
@@ -6,20 +11,157 @@ pub mod llama;
 #[cfg(feature = "llama")]
 pub use llama::LlamaModel;
 
-use anyhow::{bail, Error as E, Result};
+#[cfg(feature = "llama")]
+pub mod quantized_llama;
+
+#[cfg(feature = "llama")]
+pub use quantized_llama::QuantizedLlamaModel;
+
+#[cfg(feature = "mistral")]
+pub mod mistral;
+
+#[cfg(feature = "mistral")]
+pub use mistral::MistralModel;
+
+#[cfg(any(feature = "llama", feature = "mistral"))]
+pub mod quantized_decoder;
+
+#[cfg(any(feature = "llama", feature = "mistral"))]
+pub mod quantized_mistral;
+
+#[cfg(any(feature = "llama", feature = "mistral"))]
+pub use quantized_mistral::QuantizedMistralModel;
+
+#[cfg(feature = "mixtral")]
+pub mod mixtral;
+
+#[cfg(feature = "mixtral")]
+pub use mixtral::MixtralModel;
+
+#[cfg(feature = "qwen2")]
+pub mod qwen2;
+
+#[cfg(feature = "qwen2")]
+pub use qwen2::Qwen2Model;
+
+#[cfg(feature = "phi3")]
+pub mod phi3;
+
+#[cfg(feature = "phi3")]
+pub use phi3::Phi3Model;
+
+#[cfg(feature = "gemma")]
+pub mod gemma;
+
+#[cfg(feature = "gemma")]
+pub use gemma::GemmaModel;
+
+#[cfg(feature = "gemma2")]
+pub mod gemma2;
+
+#[cfg(feature = "gemma2")]
+pub use gemma2::Gemma2Model;
+
+use anyhow::{bail, Context, Error as E, Result};
 use cylon_config::CylonConfig;
 use cylon_inference_engine::TextGenerator;
+use gguf::find_gguf_file;
+use quantize::{estimate_quantized_size, parse_quantization};
+use utils::{load_safetensor_model_files, resolve_model_path};
 
 /// Factory function to create models based on configuration
 pub fn create_model(config: &CylonConfig) -> Result<Box<dyn TextGenerator>, E> {
+    let model_path = resolve_model_path(&config.model_path)?;
+
+    // GGUF weights are already quantized
+    if config.quantize.is_some() && find_gguf_file(&model_path)?.is_none() {
+        return create_quantized_model(config);
+    }
+
     match config.model_family.as_str() {
         #[cfg(feature = "llama")]
-        "llama" => Ok(Box::new(LlamaModel::new(config)?)),
-        
+        "llama" => match find_gguf_file(&model_path)? {
+            Some(gguf_path) => Ok(Box::new(QuantizedLlamaModel::new(config, &gguf_path)?)),
+            None => Ok(Box::new(LlamaModel::new(config)?)),
+        },
+
+        #[cfg(feature = "mistral")]
+        "mistral" => Ok(Box::new(MistralModel::new(config)?)),
+
+        #[cfg(feature = "mixtral")]
+        "mixtral" => Ok(Box::new(MixtralModel::new(config)?)),
+
+        #[cfg(feature = "qwen2")]
+        "qwen2" => Ok(Box::new(Qwen2Model::new(config)?)),
+
+        #[cfg(feature = "phi3")]
+        "phi3" => Ok(Box::new(Phi3Model::new(config)?)),
+
+        #[cfg(feature = "gemma")]
+        "gemma" => Ok(Box::new(GemmaModel::new(config)?)),
+
+        #[cfg(feature = "gemma2")]
+        "gemma2" => Ok(Box::new(Gemma2Model::new(config)?)),
+
         // Future model implementations would go here:
         // #[cfg(feature = "gpt2")]
         // "gpt2" => Ok(Box::new(Gpt2Model::new(config)?)),
         
-        _ => bail!("Unsupported model family: {}", config.model_family),
+        _ => bail!("{}", unsupported_family(&config.model_family)),
+    }
+}
+
+/// Why this build cannot serve `family`
+pub fn unsupported_family(family: &str) -> String {
+    match family {
+        // Qwen3 needs a newer candle-transformers than the one pinned in the workspace
+        "qwen3" => String::from("qwen3 is not supported by this build"),
+        _ => format!("Unsupported model family: {family}"),
     }
-}
\ No newline at end of file
+}
+
+/// Model families this build can serve
+pub fn supported_families() -> Vec<&'static str> {
+    let families = [
+        ("llama", cfg!(feature = "llama")),
+        ("mistral", cfg!(feature = "mistral")),
+        ("mixtral", cfg!(feature = "mixtral")),
+        ("qwen2", cfg!(feature = "qwen2")),
+        ("phi3", cfg!(feature = "phi3")),
+        ("gemma", cfg!(feature = "gemma")),
+        ("gemma2", cfg!(feature = "gemma2")),
+    ];
+    families.into_iter().filter(|(_, enabled)| *enabled).map(|(family, _)| family).collect()
+}
+
+/// Models whose safetensors weights are quantized while loading
+fn create_quantized_model(config: &CylonConfig) -> Result<Box<dyn TextGenerator>, E> {
+    match config.model_family.as_str() {
+        #[cfg(any(feature = "llama", feature = "mistral"))]
+        "llama" | "mistral" => Ok(Box::new(QuantizedMistralModel::new(config)?)),
+
+        _ => bail!("Quantization is not supported for model family {}", config.model_family),
+    }
+}
+
+/// Approximate memory a model needs once loaded, in bytes, from the size of its weight files
+pub fn estimate_model_size(config: &CylonConfig) -> Result<u64> {
+    let model_dir = &resolve_model_path(&config.model_path)?;
+    if let Some(gguf_path) = find_gguf_file(model_dir)? {
+        let metadata = std::fs::metadata(&gguf_path)
+            .with_context(|| format!("Failed to read {}", gguf_path.display()))?;
+        return Ok(metadata.len());
+    }
+
+    let files = load_safetensor_model_files(model_dir)
+        .with_context(|| format!("Failed to load safetensors files at {}", model_dir.display()))?;
+    if let Some(quantize) = &config.quantize {
+        return estimate_quantized_size(&files, parse_quantization(quantize)?);
+    }
+
+    files.iter().try_fold(0, |total, file| {
+        let metadata = std::fs::metadata(file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        Ok(total + metadata.len())
+    })
+}
@@ -0,0 +1,84 @@
+use crate::chat_template::TokenizerConfig;
+use crate::generator::{Family, Generator, ModelCache};
+use crate::utils::{
+    device, eos_token_handler, load_safetensor_model_files, model_dir, parse_dtype, read_model_config, use_flash_attn,
+};
+use anyhow::{Context, Error as E, Result};
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::mistral;
+use tokenizers::Tokenizer;
+use cylon_config::CylonConfig;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+/// Mistral with sliding-window attention
+///
+/// candle applies the window through the attention mask of each forward pass,
+/// so decode steps past it would attend to the whole KV cache; the window is
+/// reported as the context length to keep clients within it.
+pub type MistralModel = Generator<Mistral>;
+
+#[derive(Debug)]
+pub struct Mistral {
+    model: mistral::Model,
+    config: mistral::Config,
+}
+
+impl MistralModel {
+    pub fn new(config: &CylonConfig) -> Result<Self> {
+        let device = device()?;
+        info!("Using device: {:?}", device);
+        let dtype = parse_dtype(&config.dtype)?;
+        info!("Using dtype: {:?}", dtype);
+
+        let model_dir = &model_dir(&config.model_path)?;
+
+        let safetensors_files = load_safetensor_model_files(model_dir)
+            .with_context(|| format!("Failed to load safetensors files at {}", model_dir.display()))?;
+
+        let raw_config = read_model_config(model_dir)?;
+        let mut mistral_config: mistral::Config = serde_json::from_value(raw_config.clone())
+            .context("Failed to parse Mistral model config")?;
+        mistral_config.use_flash_attn = use_flash_attn(&device, config.use_flash_attn);
+        info!("Sliding window: {:?}", mistral_config.sliding_window);
+
+        let eos_handler = eos_token_handler(model_dir, &raw_config)?;
+
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&safetensors_files, dtype, &device)? };
+
+        let model = mistral::Model::new(&mistral_config, vb)?;
+        let tokenizer = Tokenizer::from_file(model_dir.join("tokenizer.json")).map_err(E::msg)?;
+
+        let tokenizer_config = TokenizerConfig::load(model_dir, config.chat_template.as_deref())?;
+
+        let family = Mistral { model, config: mistral_config };
+        Generator::from_parts(config, family, tokenizer, tokenizer_config, eos_handler, device, dtype)
+    }
+}
+
+impl Family for Mistral {
+    type Cache = ModelCache<mistral::Model>;
+
+    fn name(&self) -> &str {
+        "mistral"
+    }
+
+    fn context_length(&self) -> usize {
+        let max = self.config.max_position_embeddings;
+        self.config.sliding_window.map_or(max, |window| window.min(max))
+    }
+
+    fn vocab_size(&self) -> usize {
+        self.config.vocab_size
+    }
+
+    fn create_cache(&self, enable_kv_cache: bool, _dtype: DType, _device: &Device) -> Result<Self::Cache> {
+        Ok(ModelCache::new(&self.model, enable_kv_cache))
+    }
+
+    fn forward(&self, input: &Tensor, context_index: usize, cache: &mut Self::Cache) -> Result<Tensor> {
+        cache.model().forward(input, context_index)?.squeeze(1).map_err(E::from)
+    }
+}
@@ -0,0 +1,98 @@
+use crate::chat_template::TokenizerConfig;
+use crate::generator::{Family, Generator, ModelCache};
+use crate::utils::{
+    device, eos_token_handler, load_safetensor_model_files, model_dir, parse_dtype, read_model_config, use_flash_attn,
+};
+use anyhow::{Context, Error as E, Result};
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::mixtral;
+use serde::Deserialize;
+use serde_json::Value;
+use tokenizers::Tokenizer;
+use cylon_config::CylonConfig;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+/// Config fields candle keeps private
+#[derive(Debug, Deserialize)]
+struct MixtralParams {
+    vocab_size: usize,
+    max_position_embeddings: usize,
+    num_experts_per_tok: usize,
+    num_local_experts: usize,
+}
+
+/// Mixtral sparse mixture-of-experts, routing each token to `num_experts_per_tok` experts
+pub type MixtralModel = Generator<Mixtral>;
+
+#[derive(Debug)]
+pub struct Mixtral {
+    model: mixtral::Model,
+    params: MixtralParams,
+}
+
+impl MixtralModel {
+    pub fn new(config: &CylonConfig) -> Result<Self> {
+        let device = device()?;
+        info!("Using device: {:?}", device);
+        let dtype = parse_dtype(&config.dtype)?;
+        info!("Using dtype: {:?}", dtype);
+
+        let model_dir = &model_dir(&config.model_path)?;
+
+        let safetensors_files = load_safetensor_model_files(model_dir)
+            .with_context(|| format!("Failed to load safetensors files at {}", model_dir.display()))?;
+
+        let mut raw_config = read_model_config(model_dir)?;
+        let params: MixtralParams = serde_json::from_value(raw_config.clone())
+            .context("Failed to parse Mixtral model config")?;
+        info!("Experts: {} per token of {}", params.num_experts_per_tok, params.num_local_experts);
+
+        // candle's config needs both fields, which HF checkpoints omit or leave null
+        let fields = raw_config.as_object_mut().context("Mixtral model config is not an object")?;
+        fields.insert("use_flash_attn".to_string(), use_flash_attn(&device, config.use_flash_attn).into());
+        if fields.get("sliding_window").is_none_or(Value::is_null) {
+            fields.insert("sliding_window".to_string(), params.max_position_embeddings.into());
+        }
+        let mixtral_config: mixtral::Config = serde_json::from_value(raw_config.clone())
+            .context("Failed to parse Mixtral model config")?;
+
+        let eos_handler = eos_token_handler(model_dir, &raw_config)?;
+
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&safetensors_files, dtype, &device)? };
+
+        let model = mixtral::Model::new(&mixtral_config, vb)?;
+        let tokenizer = Tokenizer::from_file(model_dir.join("tokenizer.json")).map_err(E::msg)?;
+
+        let tokenizer_config = TokenizerConfig::load(model_dir, config.chat_template.as_deref())?;
+
+        let family = Mixtral { model, params };
+        Generator::from_parts(config, family, tokenizer, tokenizer_config, eos_handler, device, dtype)
+    }
+}
+
+impl Family for Mixtral {
+    type Cache = ModelCache<mixtral::Model>;
+
+    fn name(&self) -> &str {
+        "mixtral"
+    }
+
+    fn context_length(&self) -> usize {
+        self.params.max_position_embeddings
+    }
+
+    fn vocab_size(&self) -> usize {
+        self.params.vocab_size
+    }
+
+    fn create_cache(&self, enable_kv_cache: bool, _dtype: DType, _device: &Device) -> Result<Self::Cache> {
+        Ok(ModelCache::new(&self.model, enable_kv_cache))
+    }
+
+    fn forward(&self, input: &Tensor, context_index: usize, cache: &mut Self::Cache) -> Result<Tensor> {
+        cache.model().forward(input, context_index)?.squeeze(1).map_err(E::from)
+    }
+}
@@ -0,0 +1,84 @@
+use crate::chat_template::TokenizerConfig;
+use crate::generator::{Family, Generator, ModelCache};
+use crate::utils::{device, eos_token_handler, load_safetensor_model_files, model_dir, parse_dtype, read_model_config};
+use anyhow::{bail, Context, Error as E, Result};
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::phi3;
+use tokenizers::Tokenizer;
+use cylon_config::CylonConfig;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+/// Phi-3, with fused QKV and gate/up projections and `<|end|>`-terminated turns
+pub type Phi3Model = Generator<Phi3>;
+
+#[derive(Debug)]
+pub struct Phi3 {
+    model: phi3::Model,
+    config: phi3::Config,
+}
+
+impl Phi3Model {
+    pub fn new(config: &CylonConfig) -> Result<Self> {
+        let device = device()?;
+        info!("Using device: {:?}", device);
+        let dtype = parse_dtype(&config.dtype)?;
+        info!("Using dtype: {:?}", dtype);
+
+        let model_dir = &model_dir(&config.model_path)?;
+
+        let safetensors_files = load_safetensor_model_files(model_dir)
+            .with_context(|| format!("Failed to load safetensors files at {}", model_dir.display()))?;
+
+        let raw_config = read_model_config(model_dir)?;
+        // The long-context variants scale RoPE with per-dimension factors, which candle does not implement
+        if let Some(scaling) = raw_config.get("rope_scaling").filter(|scaling| !scaling.is_null()) {
+            bail!("Phi-3 rope scaling {} is not supported", scaling.get("type").unwrap_or(scaling));
+        }
+        let phi3_config: phi3::Config = serde_json::from_value(raw_config.clone())
+            .context("Failed to parse Phi-3 model config")?;
+        if config.use_flash_attn {
+            warn!("Flash attention is not supported for Phi-3, ignoring");
+        }
+
+        // Chat turns end with <|end|>, which generation_config.json lists next to </s>
+        let eos_handler = eos_token_handler(model_dir, &raw_config)?;
+        debug!("EOS tokens: {:?}", eos_handler);
+
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&safetensors_files, dtype, &device)? };
+
+        let model = phi3::Model::new(&phi3_config, vb)?;
+        let tokenizer = Tokenizer::from_file(model_dir.join("tokenizer.json")).map_err(E::msg)?;
+
+        let tokenizer_config = TokenizerConfig::load(model_dir, config.chat_template.as_deref())?;
+
+        let family = Phi3 { model, config: phi3_config };
+        Generator::from_parts(config, family, tokenizer, tokenizer_config, eos_handler, device, dtype)
+    }
+}
+
+impl Family for Phi3 {
+    type Cache = ModelCache<phi3::Model>;
+
+    fn name(&self) -> &str {
+        "phi3"
+    }
+
+    fn context_length(&self) -> usize {
+        self.config.max_position_embeddings
+    }
+
+    fn vocab_size(&self) -> usize {
+        self.config.vocab_size
+    }
+
+    fn create_cache(&self, enable_kv_cache: bool, _dtype: DType, _device: &Device) -> Result<Self::Cache> {
+        Ok(ModelCache::new(&self.model, enable_kv_cache))
+    }
+
+    fn forward(&self, input: &Tensor, context_index: usize, cache: &mut Self::Cache) -> Result<Tensor> {
+        cache.model().forward(input, context_index)?.squeeze(1).map_err(E::from)
+    }
+}
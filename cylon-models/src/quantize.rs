@@ -0,0 +1,140 @@
+use anyhow::{bail, Context, Result};
+use candle_core::quantized::{ggml_file, GgmlDType, QTensor};
+use candle_core::safetensors::MmapedSafetensors;
+use candle_core::{Device, Shape};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+/// Weight type named by the `quantize` option
+pub fn parse_quantization(quantize: &str) -> Result<GgmlDType> {
+    match quantize.to_ascii_lowercase().as_str() {
+        "q4_0" => Ok(GgmlDType::Q4_0),
+        "q4_1" => Ok(GgmlDType::Q4_1),
+        "q5_0" => Ok(GgmlDType::Q5_0),
+        "q5_1" => Ok(GgmlDType::Q5_1),
+        "q8_0" => Ok(GgmlDType::Q8_0),
+        "q2_k" => Ok(GgmlDType::Q2K),
+        "q3_k" => Ok(GgmlDType::Q3K),
+        "q4_k" => Ok(GgmlDType::Q4K),
+        "q5_k" => Ok(GgmlDType::Q5K),
+        "q6_k" => Ok(GgmlDType::Q6K),
+        _ => bail!("Unsupported quantization {quantize}"),
+    }
+}
+
+/// Name of a weight type as the `quantize` option spells it, e.g. "q4_k"
+pub fn quantization_name(dtype: GgmlDType) -> String {
+    let name = format!("{dtype:?}").to_lowercase();
+    match name.strip_suffix('k') {
+        Some(base) if base.starts_with('q') => format!("{base}_k"),
+        _ => name,
+    }
+}
+
+/// Type a tensor is stored as: matrices whose rows split into whole blocks are
+/// quantized, while norms, biases and odd-sized matrices stay f32
+fn storage_type(shape: &[usize], dtype: GgmlDType) -> GgmlDType {
+    match shape {
+        [_, columns] if columns % dtype.block_size() == 0 => dtype,
+        _ => GgmlDType::F32,
+    }
+}
+
+/// Bytes the weights of `files` take once quantized to `dtype`
+pub fn estimate_quantized_size(files: &[PathBuf], dtype: GgmlDType) -> Result<u64> {
+    let safetensors = unsafe { MmapedSafetensors::multi(files)? };
+
+    let size = safetensors
+        .tensors()
+        .iter()
+        .map(|(_, view)| quantized_tensor_size(view.shape(), dtype))
+        .sum();
+    Ok(size)
+}
+
+/// Bytes a tensor of `shape` takes once quantized to `dtype`
+pub(crate) fn quantized_tensor_size(shape: &[usize], dtype: GgmlDType) -> u64 {
+    let storage = storage_type(shape, dtype);
+    let elements: usize = shape.iter().product();
+    (elements / storage.block_size() * storage.type_size()) as u64
+}
+
+/// Quantized tensors by name, looked up under a prefix like candle's quantized VarBuilder
+#[derive(Clone)]
+pub struct QuantizedWeights {
+    tensors: Arc<HashMap<String, Arc<QTensor>>>,
+    prefix: Vec<String>,
+    device: Device,
+}
+
+impl QuantizedWeights {
+    /// Weights under `name` within the current prefix
+    pub fn pp(&self, name: impl ToString) -> Self {
+        let mut prefix = self.prefix.clone();
+        prefix.push(name.to_string());
+        QuantizedWeights { tensors: Arc::clone(&self.tensors), prefix, device: self.device.clone() }
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// The tensor `name` under the current prefix, which must have `shape`
+    pub fn get(&self, shape: impl Into<Shape>, name: &str) -> Result<Arc<QTensor>> {
+        let path = self.prefix.iter().map(String::as_str).chain([name]).collect::<Vec<_>>().join(".");
+        let tensor = self.tensors.get(&path).with_context(|| format!("Cannot find tensor {path}"))?;
+        let shape = shape.into();
+        if tensor.shape() != &shape {
+            bail!("Tensor {path} has shape {:?}, expected {shape:?}", tensor.shape());
+        }
+        Ok(Arc::clone(tensor))
+    }
+}
+
+/// Quantize the weights of safetensors files for candle's quantized layers
+///
+/// `aliases` lists `(name, source)` pairs filled from `source` when the
+/// checkpoint has no `name`, such as an output head tied to the embeddings.
+/// Tensors are quantized on the CPU one at a time and then copied to `device`.
+pub fn quantize_safetensors(files: &[PathBuf], dtype: GgmlDType, aliases: &[(&str, &str)], device: &Device) -> Result<QuantizedWeights> {
+    let safetensors = unsafe { MmapedSafetensors::multi(files)? };
+
+    let mut names: Vec<(String, String)> = safetensors
+        .tensors()
+        .into_iter()
+        .map(|(name, _)| (name.clone(), name))
+        .collect();
+    for (name, source) in aliases {
+        if !names.iter().any(|(existing, _)| existing == name) {
+            names.push((name.to_string(), source.to_string()));
+        }
+    }
+
+    let mut quantized = 0;
+    let tensors = names
+        .into_iter()
+        .map(|(name, source)| {
+            let tensor = safetensors
+                .load(&source, &Device::Cpu)
+                .with_context(|| format!("Failed to load tensor {source}"))?;
+            let storage = storage_type(tensor.dims(), dtype);
+            if storage == dtype {
+                quantized += 1;
+            }
+            let mut tensor = QTensor::quantize(&tensor, storage)
+                .with_context(|| format!("Failed to quantize tensor {name}"))?;
+            if !device.is_cpu() {
+                tensor = ggml_file::qtensor_from_ggml(storage, &tensor.data()?, tensor.shape().dims().to_vec(), device)
+                    .with_context(|| format!("Failed to copy tensor {name} to {device:?}"))?;
+            }
+            Ok((name, Arc::new(tensor)))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+    info!("Quantized {} of {} tensors to {}", quantized, tensors.len(), quantization_name(dtype));
+
+    Ok(QuantizedWeights { tensors: Arc::new(tensors), prefix: Vec::new(), device: device.clone() })
+}
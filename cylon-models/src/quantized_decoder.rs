@@ -0,0 +1,276 @@
+use crate::quantize::QuantizedWeights;
+use anyhow::Result;
+use candle_core::{DType, Device, Module, Tensor, D};
+use candle_nn::{Activation, Embedding, RmsNorm};
+use candle_transformers::models::mistral::Config;
+use candle_transformers::quantized_nn::Linear;
+use candle_transformers::utils::repeat_kv;
+use std::sync::Arc;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+// The decoder of candle's quantized Mistral, built from weights quantized in
+// memory rather than from a GGUF file
+
+fn linear_no_bias(in_dim: usize, out_dim: usize, weights: QuantizedWeights) -> Result<Linear> {
+    Ok(Linear::from_arc(weights.get((out_dim, in_dim), "weight")?, None)?)
+}
+
+fn rms_norm(size: usize, eps: f64, weights: QuantizedWeights) -> Result<RmsNorm> {
+    let weight = weights.get(size, "weight")?.dequantize(weights.device())?;
+    Ok(RmsNorm::new(weight, eps))
+}
+
+#[derive(Debug, Clone)]
+struct RotaryEmbedding {
+    sin: Tensor,
+    cos: Tensor,
+}
+
+impl RotaryEmbedding {
+    fn new(cfg: &Config, dev: &Device) -> Result<Self> {
+        let rope_theta = cfg.rope_theta as f32;
+        let dim = cfg.hidden_size / cfg.num_attention_heads;
+        let max_seq_len = cfg.max_position_embeddings;
+        let inv_freq: Vec<_> = (0..dim)
+            .step_by(2)
+            .map(|i| 1f32 / rope_theta.powf(i as f32 / dim as f32))
+            .collect();
+        let inv_freq_len = inv_freq.len();
+        let inv_freq = Tensor::from_vec(inv_freq, (1, inv_freq_len), dev)?;
+        let t = Tensor::arange(0u32, max_seq_len as u32, dev)?
+            .to_dtype(DType::F32)?
+            .reshape((max_seq_len, 1))?;
+        let freqs = t.matmul(&inv_freq)?;
+        Ok(Self { sin: freqs.sin()?, cos: freqs.cos()? })
+    }
+
+    fn apply_rotary_emb_qkv(&self, q: &Tensor, k: &Tensor, seqlen_offset: usize) -> candle_core::Result<(Tensor, Tensor)> {
+        let (_b_sz, _h, seq_len, _n_embd) = q.dims4()?;
+        let cos = self.cos.narrow(0, seqlen_offset, seq_len)?;
+        let sin = self.sin.narrow(0, seqlen_offset, seq_len)?;
+        let q_embed = candle_nn::rotary_emb::rope(q, &cos, &sin)?;
+        let k_embed = candle_nn::rotary_emb::rope(k, &cos, &sin)?;
+        Ok((q_embed, k_embed))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Mlp {
+    gate_proj: Linear,
+    up_proj: Linear,
+    down_proj: Linear,
+    act_fn: Activation,
+}
+
+impl Mlp {
+    fn new(cfg: &Config, weights: QuantizedWeights) -> Result<Self> {
+        let hidden_sz = cfg.hidden_size;
+        let intermediate_sz = cfg.intermediate_size;
+        Ok(Self {
+            gate_proj: linear_no_bias(hidden_sz, intermediate_sz, weights.pp("gate_proj"))?,
+            up_proj: linear_no_bias(hidden_sz, intermediate_sz, weights.pp("up_proj"))?,
+            down_proj: linear_no_bias(intermediate_sz, hidden_sz, weights.pp("down_proj"))?,
+            act_fn: cfg.hidden_act,
+        })
+    }
+}
+
+impl Module for Mlp {
+    fn forward(&self, xs: &Tensor) -> candle_core::Result<Tensor> {
+        let lhs = xs.apply(&self.gate_proj)?.apply(&self.act_fn)?;
+        let rhs = xs.apply(&self.up_proj)?;
+        (lhs * rhs)?.apply(&self.down_proj)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Attention {
+    q_proj: Linear,
+    k_proj: Linear,
+    v_proj: Linear,
+    o_proj: Linear,
+    num_heads: usize,
+    num_kv_heads: usize,
+    num_kv_groups: usize,
+    head_dim: usize,
+    hidden_size: usize,
+    rotary_emb: Arc<RotaryEmbedding>,
+    kv_cache: Option<(Tensor, Tensor)>,
+}
+
+impl Attention {
+    fn new(rotary_emb: Arc<RotaryEmbedding>, cfg: &Config, weights: QuantizedWeights) -> Result<Self> {
+        let hidden_sz = cfg.hidden_size;
+        let num_heads = cfg.num_attention_heads;
+        let num_kv_heads = cfg.num_key_value_heads;
+        let head_dim = hidden_sz / num_heads;
+        Ok(Self {
+            q_proj: linear_no_bias(hidden_sz, num_heads * head_dim, weights.pp("q_proj"))?,
+            k_proj: linear_no_bias(hidden_sz, num_kv_heads * head_dim, weights.pp("k_proj"))?,
+            v_proj: linear_no_bias(hidden_sz, num_kv_heads * head_dim, weights.pp("v_proj"))?,
+            o_proj: linear_no_bias(num_heads * head_dim, hidden_sz, weights.pp("o_proj"))?,
+            num_heads,
+            num_kv_heads,
+            num_kv_groups: num_heads / num_kv_heads,
+            head_dim,
+            hidden_size: hidden_sz,
+            rotary_emb,
+            kv_cache: None,
+        })
+    }
+
+    fn forward(&mut self, xs: &Tensor, attention_mask: Option<&Tensor>, seqlen_offset: usize) -> candle_core::Result<Tensor> {
+        let (b_sz, q_len, _) = xs.dims3()?;
+
+        let query_states = self.q_proj.forward(xs)?;
+        let key_states = self.k_proj.forward(xs)?;
+        let value_states = self.v_proj.forward(xs)?;
+
+        let query_states = query_states
+            .reshape((b_sz, q_len, self.num_heads, self.head_dim))?
+            .transpose(1, 2)?
+            .contiguous()?;
+        let key_states = key_states
+            .reshape((b_sz, q_len, self.num_kv_heads, self.head_dim))?
+            .transpose(1, 2)?
+            .contiguous()?;
+        let value_states = value_states
+            .reshape((b_sz, q_len, self.num_kv_heads, self.head_dim))?
+            .transpose(1, 2)?;
+
+        let (query_states, key_states) = self.rotary_emb.apply_rotary_emb_qkv(&query_states, &key_states, seqlen_offset)?;
+
+        let (key_states, value_states) = match &self.kv_cache {
+            None => (key_states, value_states),
+            Some((prev_k, prev_v)) => {
+                let key_states = Tensor::cat(&[prev_k, &key_states], 2)?;
+                let value_states = Tensor::cat(&[prev_v, &value_states], 2)?;
+                (key_states, value_states)
+            }
+        };
+        self.kv_cache = Some((key_states.clone(), value_states.clone()));
+
+        let key_states = repeat_kv(key_states, self.num_kv_groups)?;
+        let value_states = repeat_kv(value_states, self.num_kv_groups)?;
+
+        let attn_output = {
+            let scale = 1f64 / f64::sqrt(self.head_dim as f64);
+            let attn_weights = (query_states.matmul(&key_states.transpose(2, 3)?)? * scale)?;
+
+            let attn_weights = match attention_mask {
+                None => attn_weights,
+                Some(mask) => attn_weights.broadcast_add(mask)?,
+            };
+            let attn_weights = candle_nn::ops::softmax_last_dim(&attn_weights)?;
+            attn_weights.matmul(&value_states)?
+        };
+        attn_output
+            .transpose(1, 2)?
+            .reshape((b_sz, q_len, self.hidden_size))?
+            .apply(&self.o_proj)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct DecoderLayer {
+    self_attn: Attention,
+    mlp: Mlp,
+    input_layernorm: RmsNorm,
+    post_attention_layernorm: RmsNorm,
+}
+
+impl DecoderLayer {
+    fn new(rotary_emb: Arc<RotaryEmbedding>, cfg: &Config, weights: QuantizedWeights) -> Result<Self> {
+        Ok(Self {
+            self_attn: Attention::new(rotary_emb, cfg, weights.pp("self_attn"))?,
+            mlp: Mlp::new(cfg, weights.pp("mlp"))?,
+            input_layernorm: rms_norm(cfg.hidden_size, cfg.rms_norm_eps, weights.pp("input_layernorm"))?,
+            post_attention_layernorm: rms_norm(cfg.hidden_size, cfg.rms_norm_eps, weights.pp("post_attention_layernorm"))?,
+        })
+    }
+
+    fn forward(&mut self, xs: &Tensor, attention_mask: Option<&Tensor>, seqlen_offset: usize) -> candle_core::Result<Tensor> {
+        let residual = xs;
+        let xs = self.input_layernorm.forward(xs)?;
+        let xs = self.self_attn.forward(&xs, attention_mask, seqlen_offset)?;
+        let xs = (xs + residual)?;
+        let residual = &xs;
+        let xs = xs.apply(&self.post_attention_layernorm)?.apply(&self.mlp)?;
+        residual + xs
+    }
+}
+
+/// Mistral decoder over quantized weights with the Hugging Face tensor names
+#[derive(Debug, Clone)]
+pub struct QuantizedDecoder {
+    embed_tokens: Embedding,
+    layers: Vec<DecoderLayer>,
+    norm: RmsNorm,
+    lm_head: Linear,
+    sliding_window: Option<usize>,
+    device: Device,
+}
+
+impl QuantizedDecoder {
+    pub fn new(cfg: &Config, weights: &QuantizedWeights) -> Result<Self> {
+        let model = weights.pp("model");
+        let embeddings = model
+            .get((cfg.vocab_size, cfg.hidden_size), "embed_tokens.weight")?
+            .dequantize(weights.device())?;
+        let rotary_emb = Arc::new(RotaryEmbedding::new(cfg, weights.device())?);
+        let layers = (0..cfg.num_hidden_layers)
+            .map(|layer_idx| DecoderLayer::new(Arc::clone(&rotary_emb), cfg, model.pp("layers").pp(layer_idx)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            embed_tokens: Embedding::new(embeddings, cfg.hidden_size),
+            layers,
+            norm: rms_norm(cfg.hidden_size, cfg.rms_norm_eps, model.pp("norm"))?,
+            lm_head: linear_no_bias(cfg.hidden_size, cfg.vocab_size, weights.pp("lm_head"))?,
+            sliding_window: cfg.sliding_window,
+            device: weights.device().clone(),
+        })
+    }
+
+    fn prepare_decoder_attention_mask(&self, tgt_len: usize, seqlen_offset: usize) -> candle_core::Result<Tensor> {
+        let sliding_window = self.sliding_window.unwrap_or(tgt_len + 1);
+        let mask: Vec<_> = (0..tgt_len)
+            .flat_map(|i| {
+                (0..tgt_len).map(move |j| {
+                    if i < j || j + sliding_window < i {
+                        f32::NEG_INFINITY
+                    } else {
+                        0.
+                    }
+                })
+            })
+            .collect();
+        let mask = Tensor::from_slice(&mask, (tgt_len, tgt_len), &self.device)?;
+        let mask = if seqlen_offset > 0 {
+            let mask0 = Tensor::zeros((tgt_len, seqlen_offset), DType::F32, &self.device)?;
+            Tensor::cat(&[&mask0, &mask], D::Minus1)?
+        } else {
+            mask
+        };
+        mask.expand((1, 1, tgt_len, tgt_len + seqlen_offset))?.to_dtype(DType::F32)
+    }
+
+    /// Logits for the last position of `input_ids`, which start at `seqlen_offset`
+    pub fn forward(&mut self, input_ids: &Tensor, seqlen_offset: usize) -> candle_core::Result<Tensor> {
+        let (_b_size, seq_len) = input_ids.dims2()?;
+        let attention_mask = if seq_len <= 1 {
+            None
+        } else {
+            Some(self.prepare_decoder_attention_mask(seq_len, seqlen_offset)?)
+        };
+        let mut xs = self.embed_tokens.forward(input_ids)?;
+        for layer in self.layers.iter_mut() {
+            xs = layer.forward(&xs, attention_mask.as_ref(), seqlen_offset)?
+        }
+        xs.narrow(1, seq_len - 1, 1)?
+            .contiguous()?
+            .apply(&self.norm)?
+            .apply(&self.lm_head)
+    }
+}
@@ -0,0 +1,95 @@
+use crate::gguf::{load_tokenizer, load_tokenizer_config, read_gguf, Metadata};
+use crate::generator::{Family, Generator, ModelCache};
+use crate::quantize::quantization_name;
+use crate::utils::device;
+use anyhow::{Context, Error as E, Result};
+use candle_core::{DType, Device, Tensor};
+use candle_transformers::models::quantized_llama;
+use std::path::Path;
+use cylon_config::CylonConfig;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+/// Longest context candle's quantized llama precomputes rotary embeddings for
+const MAX_SEQ_LEN: usize = 4096;
+
+/// Llama with quantized weights from a GGUF file, such as Q4_K or Q8_0
+///
+/// Weights stay quantized and are multiplied in place, so a Q4_K 8B model needs
+/// about a quarter of the memory of its f16 safetensors. Activations are computed
+/// in f32, and the quantization of the attention weights is reported as the dtype.
+pub type QuantizedLlamaModel = Generator<QuantizedLlama>;
+
+#[derive(Debug)]
+pub struct QuantizedLlama {
+    model: quantized_llama::ModelWeights,
+    context_length: usize,
+    vocab_size: usize,
+}
+
+impl QuantizedLlamaModel {
+    pub fn new(config: &CylonConfig, gguf_path: &Path) -> Result<Self> {
+        let device = device()?;
+        info!("Using device: {:?}", device);
+        if config.quantize.is_some() {
+            warn!("GGUF weights are already quantized, ignoring quantize");
+        }
+        if config.use_flash_attn {
+            warn!("Flash attention is not supported for GGUF models, ignoring");
+        }
+
+        let model_dir = gguf_path.parent().unwrap_or(Path::new("."));
+        let (content, mut file) = read_gguf(gguf_path)?;
+        let metadata = Metadata(&content.metadata);
+
+        let weight_type = content
+            .tensor_infos
+            .get("blk.0.attn_q.weight")
+            .map(|info| quantization_name(info.ggml_dtype))
+            .unwrap_or_default();
+        info!("Using GGUF weights: {}", weight_type);
+
+        let context_length = metadata
+            .opt_u32("llama.context_length")
+            .map_or(MAX_SEQ_LEN, |length| (length as usize).min(MAX_SEQ_LEN));
+        let vocab_size = metadata.tokens()?.len();
+
+        let eos_handler = metadata.eos_token_handler();
+        debug!("EOS tokens: {:?}", eos_handler);
+
+        let tokenizer = load_tokenizer(model_dir, &metadata)?;
+        let tokenizer_config = load_tokenizer_config(model_dir, &metadata, config.chat_template.as_deref())?;
+
+        let model = quantized_llama::ModelWeights::from_gguf(content, &mut file, &device)
+            .with_context(|| format!("Failed to load GGUF model at {}", gguf_path.display()))?;
+
+        let family = QuantizedLlama { model, context_length, vocab_size };
+        let generator = Generator::from_parts(config, family, tokenizer, tokenizer_config, eos_handler, device, DType::F32)?;
+        Ok(generator.with_weight_type(weight_type))
+    }
+}
+
+impl Family for QuantizedLlama {
+    type Cache = ModelCache<quantized_llama::ModelWeights>;
+
+    fn name(&self) -> &str {
+        "llama"
+    }
+
+    fn context_length(&self) -> usize {
+        self.context_length
+    }
+
+    fn vocab_size(&self) -> usize {
+        self.vocab_size
+    }
+
+    fn create_cache(&self, enable_kv_cache: bool, _dtype: DType, _device: &Device) -> Result<Self::Cache> {
+        Ok(ModelCache::new(&self.model, enable_kv_cache))
+    }
+
+    fn forward(&self, input: &Tensor, context_index: usize, cache: &mut Self::Cache) -> Result<Tensor> {
+        cache.model().forward(input, context_index).map_err(E::from)
+    }
+}
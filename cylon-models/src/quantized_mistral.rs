@@ -0,0 +1,122 @@
+use crate::chat_template::TokenizerConfig;
+use crate::generator::{Family, Generator, ModelCache};
+use crate::quantize::{parse_quantization, quantization_name, quantize_safetensors};
+use crate::quantized_decoder::QuantizedDecoder;
+use crate::utils::{device, eos_token_handler, load_safetensor_model_files, model_dir, read_model_config, resident_memory};
+use anyhow::{bail, Context, Error as E, Result};
+use candle_core::{DType, Device, Tensor};
+use candle_transformers::models::mistral;
+use serde_json::Value;
+use tokenizers::Tokenizer;
+use cylon_config::CylonConfig;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+const MB: u64 = 1024 * 1024;
+
+/// Llama or Mistral safetensors checkpoint whose weights are quantized while loading
+///
+/// Both share the decoder architecture of candle's quantized Mistral, which
+/// takes the Hugging Face tensor names. Activations are computed in f32, and
+/// the quantization is reported as the dtype.
+pub type QuantizedMistralModel = Generator<QuantizedMistral>;
+
+#[derive(Debug)]
+pub struct QuantizedMistral {
+    model: QuantizedDecoder,
+    config: mistral::Config,
+    family: String,
+}
+
+impl QuantizedMistralModel {
+    pub fn new(config: &CylonConfig) -> Result<Self> {
+        let device = device()?;
+        info!("Using device: {:?}", device);
+        let quantize = config.quantize.as_deref().context("No quantization configured")?;
+        let weight_type = parse_quantization(quantize)?;
+        if config.use_flash_attn {
+            warn!("Flash attention is not supported for quantized models, ignoring");
+        }
+
+        let model_dir = &model_dir(&config.model_path)?;
+
+        let safetensors_files = load_safetensor_model_files(model_dir)
+            .with_context(|| format!("Failed to load safetensors files at {}", model_dir.display()))?;
+
+        let raw_config = read_model_config(model_dir)?;
+        let mistral_config = mistral_config(&config.model_family, raw_config.clone())?;
+
+        let eos_handler = eos_token_handler(model_dir, &raw_config)?;
+
+        let memory_before = resident_memory();
+        let mut aliases = Vec::new();
+        if raw_config.get("tie_word_embeddings").and_then(Value::as_bool).unwrap_or(false) {
+            aliases.push(("lm_head.weight", "model.embed_tokens.weight"));
+        }
+        let weights = quantize_safetensors(&safetensors_files, weight_type, &aliases, &device)?;
+        let model = QuantizedDecoder::new(&mistral_config, &weights)?;
+        drop(weights);
+        if let (Some(before), Some(after)) = (memory_before, resident_memory()) {
+            info!("Resident memory {} MB before quantizing, {} MB after", before / MB, after / MB);
+        }
+
+        let tokenizer = Tokenizer::from_file(model_dir.join("tokenizer.json")).map_err(E::msg)?;
+
+        let tokenizer_config = TokenizerConfig::load(model_dir, config.chat_template.as_deref())?;
+
+        let family = QuantizedMistral { model, config: mistral_config, family: config.model_family.clone() };
+        let generator = Generator::from_parts(config, family, tokenizer, tokenizer_config, eos_handler, device, DType::F32)?;
+        Ok(generator.with_weight_type(quantization_name(weight_type)))
+    }
+}
+
+/// Read a Mistral or Llama `config.json` as a Mistral config
+fn mistral_config(family: &str, mut raw_config: Value) -> Result<mistral::Config> {
+    if family == "llama" {
+        let Some(fields) = raw_config.as_object_mut() else {
+            bail!("Model config is not a JSON object");
+        };
+        // Llama configs may leave these at the transformers defaults
+        if !fields.contains_key("num_key_value_heads") {
+            let heads = fields.get("num_attention_heads").cloned().unwrap_or(Value::Null);
+            fields.insert("num_key_value_heads".to_string(), heads);
+        }
+        if !fields.contains_key("rope_theta") {
+            fields.insert("rope_theta".to_string(), 10000.0.into());
+        }
+        // The quantized decoder has no RoPE scaling, and Llama 3.1 scaling changes the frequencies at every position
+        if let Some(scaling) = fields.get("rope_scaling").filter(|scaling| !scaling.is_null()) {
+            let kind = scaling.get("rope_type").or_else(|| scaling.get("type")).unwrap_or(scaling);
+            bail!("Llama rope scaling {} is not supported with quantize; load the model without quantize", kind);
+        }
+    }
+
+    serde_json::from_value(raw_config).context("Failed to parse model config")
+}
+
+impl Family for QuantizedMistral {
+    type Cache = ModelCache<QuantizedDecoder>;
+
+    fn name(&self) -> &str {
+        &self.family
+    }
+
+    /// Capped at Mistral's attention window, which decode steps do not apply
+    fn context_length(&self) -> usize {
+        let max = self.config.max_position_embeddings;
+        self.config.sliding_window.map_or(max, |window| window.min(max))
+    }
+
+    fn vocab_size(&self) -> usize {
+        self.config.vocab_size
+    }
+
+    fn create_cache(&self, enable_kv_cache: bool, _dtype: DType, _device: &Device) -> Result<Self::Cache> {
+        Ok(ModelCache::new(&self.model, enable_kv_cache))
+    }
+
+    fn forward(&self, input: &Tensor, context_index: usize, cache: &mut Self::Cache) -> Result<Tensor> {
+        cache.model().forward(input, context_index)?.squeeze(1).map_err(E::from)
+    }
+}
@@ -0,0 +1,86 @@
+use crate::chat_template::{TokenizerConfig, CHATML_TEMPLATE};
+use crate::generator::{Family, Generator, ModelCache};
+use crate::utils::{device, eos_token_handler, load_safetensor_model_files, model_dir, parse_dtype, read_model_config};
+use anyhow::{Context, Error as E, Result};
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::qwen2;
+use tokenizers::Tokenizer;
+use cylon_config::CylonConfig;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+/// Qwen2 and Qwen2.5, prompted with ChatML (`<|im_start|>role ... <|im_end|>`) framing
+///
+/// Qwen has no BOS token; the chat template provides all the framing.
+pub type Qwen2Model = Generator<Qwen2>;
+
+#[derive(Debug)]
+pub struct Qwen2 {
+    model: qwen2::ModelForCausalLM,
+    config: qwen2::Config,
+}
+
+impl Qwen2Model {
+    pub fn new(config: &CylonConfig) -> Result<Self> {
+        let device = device()?;
+        info!("Using device: {:?}", device);
+        let dtype = parse_dtype(&config.dtype)?;
+        info!("Using dtype: {:?}", dtype);
+
+        let model_dir = &model_dir(&config.model_path)?;
+
+        let safetensors_files = load_safetensor_model_files(model_dir)
+            .with_context(|| format!("Failed to load safetensors files at {}", model_dir.display()))?;
+
+        let raw_config = read_model_config(model_dir)?;
+        let mut qwen_config: qwen2::Config = serde_json::from_value(raw_config.clone())
+            .context("Failed to parse Qwen2 model config")?;
+        if config.use_flash_attn {
+            warn!("Flash attention is not supported for Qwen2, ignoring");
+        }
+        // candle always masks with the window, which checkpoints usually leave disabled
+        if !qwen_config.use_sliding_window {
+            qwen_config.sliding_window = qwen_config.max_position_embeddings;
+        }
+
+        // Instruct checkpoints end turns with <|im_end|> and list it next to <|endoftext|>
+        let eos_handler = eos_token_handler(model_dir, &raw_config)?;
+        debug!("EOS tokens: {:?}", eos_handler);
+
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&safetensors_files, dtype, &device)? };
+
+        let model = qwen2::ModelForCausalLM::new(&qwen_config, vb)?;
+        let tokenizer = Tokenizer::from_file(model_dir.join("tokenizer.json")).map_err(E::msg)?;
+
+        let tokenizer_config = TokenizerConfig::load(model_dir, config.chat_template.as_deref())?.with_default_template(CHATML_TEMPLATE)?;
+
+        let family = Qwen2 { model, config: qwen_config };
+        Generator::from_parts(config, family, tokenizer, tokenizer_config, eos_handler, device, dtype)
+    }
+}
+
+impl Family for Qwen2 {
+    type Cache = ModelCache<qwen2::ModelForCausalLM>;
+
+    fn name(&self) -> &str {
+        "qwen2"
+    }
+
+    fn context_length(&self) -> usize {
+        self.config.max_position_embeddings
+    }
+
+    fn vocab_size(&self) -> usize {
+        self.config.vocab_size
+    }
+
+    fn create_cache(&self, enable_kv_cache: bool, _dtype: DType, _device: &Device) -> Result<Self::Cache> {
+        Ok(ModelCache::new(&self.model, enable_kv_cache))
+    }
+
+    fn forward(&self, input: &Tensor, context_index: usize, cache: &mut Self::Cache) -> Result<Tensor> {
+        cache.model().forward(input, context_index)?.squeeze(1).map_err(E::from)
+    }
+}
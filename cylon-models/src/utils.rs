@@ -1,10 +1,12 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use candle_core::utils::{cuda_is_available, metal_is_available};
 use candle_core::DType;
-use candle_core::Device;
+use candle_core::{Device, DeviceLocation};
 use serde_json::Value;
 use std::fs::File;
 use std::path::{Path, PathBuf};
+use cylon_config::CylonConfig;
+use cylon_inference_engine::{EosTokenHandler, InferenceConfig};
 
 #[allow(unused_imports)]
 use tracing::{info, debug, error, warn};
@@ -22,6 +24,183 @@ pub fn device() -> Result<Device> {
     }
 }
 
+/// Short name of the device a model is loaded on, e.g. "cpu" or "cuda:0"
+pub fn device_name(device: &Device) -> String {
+    match device.location() {
+        DeviceLocation::Cpu => String::from("cpu"),
+        DeviceLocation::Cuda { gpu_id } => format!("cuda:{gpu_id}"),
+        DeviceLocation::Metal { gpu_id } => format!("metal:{gpu_id}"),
+    }
+}
+
+/// Resident memory of this process in bytes, where the platform reports it
+pub fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// The model directory, checked to exist
+pub fn model_dir(model_path: &str) -> Result<PathBuf> {
+    let model_dir = resolve_model_path(model_path)?;
+
+    if !model_dir.is_dir() {
+        bail!("Model path is not a directory: {}", model_dir.display());
+    }
+
+    Ok(model_dir)
+}
+
+/// Local path of a model: `model_path` itself when it exists, otherwise a
+/// Hugging Face repo id such as `org/name` or `org/name@revision`, looked up
+/// in the local hub cache without going online
+pub fn resolve_model_path(model_path: &str) -> Result<PathBuf> {
+    let path = Path::new(model_path);
+    if path.exists() {
+        return Ok(path.to_path_buf());
+    }
+
+    let (repo_id, revision) = model_path.split_once('@').unwrap_or((model_path, "main"));
+    let Some((org, name)) = repo_id.split_once('/').filter(|(org, name)| {
+        !org.is_empty() && !org.starts_with('.') && !name.is_empty() && !name.contains('/')
+    }) else {
+        bail!("Model path does not exist: {}", path.display());
+    };
+
+    let cache_dir = hf_hub_cache_dir().context("Cannot locate the Hugging Face cache; set HF_HUB_CACHE")?;
+    let repo_dir = cache_dir.join(format!("models--{org}--{name}"));
+    if !repo_dir.is_dir() {
+        bail!("Model path does not exist and {} is not in the Hugging Face cache at {}", repo_id, cache_dir.display());
+    }
+
+    // Branches and tags are refs naming a commit; a commit hash names its snapshot directly
+    let commit = match std::fs::read_to_string(repo_dir.join("refs").join(revision)) {
+        Ok(commit) => commit.trim().to_string(),
+        Err(_) => revision.to_string(),
+    };
+    let snapshot = repo_dir.join("snapshots").join(&commit);
+    if !snapshot.is_dir() {
+        bail!("Revision {} of {} is not in the Hugging Face cache at {}", revision, repo_id, cache_dir.display());
+    }
+
+    debug!("Resolved model {}@{} to {}", repo_id, revision, snapshot.display());
+    Ok(snapshot)
+}
+
+/// The Hugging Face hub cache, located through the same environment variables as `huggingface_hub`
+fn hf_hub_cache_dir() -> Option<PathBuf> {
+    let var = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+
+    var("HF_HUB_CACHE")
+        .or_else(|| var("HUGGINGFACE_HUB_CACHE"))
+        .or_else(|| var("HF_HOME").map(|home| home.join("hub")))
+        .or_else(|| var("XDG_CACHE_HOME").map(|cache| cache.join("huggingface").join("hub")))
+        .or_else(|| var("HOME").map(|home| home.join(".cache").join("huggingface").join("hub")))
+}
+
+/// Whether to use flash attention, which is only available on CUDA
+pub fn use_flash_attn(device: &Device, requested: bool) -> bool {
+    match device {
+        Device::Metal(_) => {
+            if requested {
+                warn!("Flash attention is not supported on Metal, disabling");
+            }
+            false
+        },
+        Device::Cuda(_) => requested,
+        _ => false,
+    }
+}
+
+/// Read a model's `config.json` as untyped JSON
+pub fn read_model_config(model_dir: &Path) -> Result<Value> {
+    let path = model_dir.join("config.json");
+    let file = File::open(&path)
+        .with_context(|| format!("Failed to open model config file at {}", path.display()))?;
+    serde_json::from_reader(&file)
+        .with_context(|| format!("Failed to parse model config file at {}", path.display()))
+}
+
+/// EOS tokens from `eos_token_id` in the model config, merged with those in
+/// `generation_config.json` when the model ships one
+pub fn eos_token_handler(model_dir: &Path, config: &Value) -> Result<EosTokenHandler> {
+    let mut ids = eos_token_ids(config);
+
+    if let Some(generation_config) = read_generation_config(model_dir)? {
+        for id in eos_token_ids(&generation_config) {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+    }
+
+    Ok(match ids.as_slice() {
+        [] => EosTokenHandler::None,
+        [id] => EosTokenHandler::Single(*id),
+        _ => EosTokenHandler::Multiple(ids),
+    })
+}
+
+/// A model's `generation_config.json`, when it ships one
+fn read_generation_config(model_dir: &Path) -> Result<Option<Value>> {
+    let path = model_dir.join("generation_config.json");
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let file = File::open(&path)
+        .with_context(|| format!("Failed to open generation config at {}", path.display()))?;
+    let generation_config = serde_json::from_reader(&file)
+        .with_context(|| format!("Failed to parse generation config at {}", path.display()))?;
+    Ok(Some(generation_config))
+}
+
+/// Sampling defaults for a model: the configured values, else those recommended
+/// by its `generation_config.json`, else greedy decoding without a repeat penalty
+///
+/// Like transformers, the recommended temperature, top_p and top_k only apply
+/// when the generation config sets `do_sample`.
+pub fn sampling_defaults(config: &CylonConfig) -> Result<InferenceConfig> {
+    let model_path = resolve_model_path(&config.model_path)?;
+    let model_dir = match model_path.is_file() {
+        true => model_path.parent().unwrap_or(Path::new(".")),
+        false => &model_path,
+    };
+    let generation_config = read_generation_config(model_dir)?.unwrap_or(Value::Null);
+
+    let sampled = |key: &str| match generation_config.get("do_sample").and_then(Value::as_bool) {
+        Some(true) => generation_config.get(key).filter(|value| !value.is_null()),
+        _ => None,
+    };
+    let temperature = sampled("temperature").and_then(Value::as_f64);
+    let top_p = sampled("top_p").and_then(Value::as_f64);
+    let top_k = sampled("top_k").and_then(Value::as_u64).map(|k| k as usize);
+    let repeat_penalty = generation_config.get("repetition_penalty").and_then(Value::as_f64).map(|p| p as f32);
+
+    let defaults = InferenceConfig {
+        temperature: config.temperature.or(temperature).unwrap_or(0.0),
+        top_k: config.top_k.or(top_k).filter(|k| *k > 0),
+        top_p: config.top_p.or(top_p).filter(|p| *p > 0.0),
+        seed: Some(config.seed),
+        repeat_penalty: config.repeat_penalty.or(repeat_penalty).unwrap_or(1.0),
+        repeat_last_n: config.repeat_last_n,
+    };
+    if generation_config.is_object() {
+        debug!("Sampling defaults for {} with its generation config: {:?}", config.model_path, defaults);
+    }
+    Ok(defaults)
+}
+
+/// The `eos_token_id` field of a config, a single id or a list
+fn eos_token_ids(config: &Value) -> Vec<u32> {
+    match config.get("eos_token_id") {
+        Some(Value::Number(id)) => id.as_u64().map(|id| id as u32).into_iter().collect(),
+        Some(Value::Array(ids)) => ids.iter().filter_map(Value::as_u64).map(|id| id as u32).collect(),
+        _ => Vec::new(),
+    }
+}
+
 pub fn parse_dtype(dtype: &Option<String>) -> Result<DType> {
     match dtype.as_deref() {
         Some("f16") => Ok(DType::F16),
@@ -32,16 +211,27 @@ pub fn parse_dtype(dtype: &Option<String>) -> Result<DType> {
     }
 }
 
+/// Weight files of a checkpoint: the shards listed in `model.safetensors.index.json`,
+/// or a single `model.safetensors`
 pub fn load_safetensor_model_files(model_path: &Path) -> Result<Vec<PathBuf>> {
-    let model_index_file = File::open(model_path.join("model.safetensors.index.json"))?;
+    let index_path = model_path.join("model.safetensors.index.json");
+    if !index_path.exists() {
+        let single_file = model_path.join("model.safetensors");
+        if !single_file.exists() {
+            bail!("Neither model.safetensors.index.json nor model.safetensors found in {}", model_path.display());
+        }
+        return Ok(vec![single_file]);
+    }
+
+    let model_index_file = File::open(&index_path)?;
     let model_index_json: Value = serde_json::from_reader(&model_index_file).map_err(candle_core::Error::wrap)?;
     let model_weight_map = match model_index_json.get("weight_map") {
-        None => bail!("no weight map in {model_index_file:?}"),
+        None => bail!("no weight map in {}", index_path.display()),
         Some(Value::Object(map)) => map,
-        Some(_) => bail!("weight map in {model_index_file:?} is not a map"),
+        Some(_) => bail!("weight map in {} is not a map", index_path.display()),
     };
 
-    let mut safetensors_files = std::collections::HashSet::new();
+    let mut safetensors_files = std::collections::BTreeSet::new();
     for value in model_weight_map.values() {
         if let Some(file) = value.as_str() {
             safetensors_files.insert(file.to_string());
@@ -53,5 +243,64 @@ pub fn load_safetensor_model_files(model_path: &Path) -> Result<Vec<PathBuf>> {
         .map(|v| model_path.join(v))
         .collect();
 
+    if let Some(missing) = safetensors_files.iter().find(|file| !file.exists()) {
+        bail!("Missing shard {} listed in {}", missing.display(), index_path.display());
+    }
+
     Ok(safetensors_files)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+
+    /// EOS tokens for `config`, with `generation_config` written next to it when given
+    fn eos_token_handler_with(test: &str, config: Value, generation_config: Option<Value>) -> EosTokenHandler {
+        let dir = std::env::temp_dir().join(format!("cylon-eos-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        if let Some(generation_config) = generation_config {
+            fs::write(dir.join("generation_config.json"), generation_config.to_string()).unwrap();
+        }
+        let eos_handler = eos_token_handler(&dir, &config);
+        fs::remove_dir_all(&dir).unwrap();
+        eos_handler.unwrap()
+    }
+
+    #[test]
+    fn reads_a_single_eos_token() {
+        let eos = eos_token_handler_with("single", json!({"eos_token_id": 2}), None);
+        assert!(matches!(eos, EosTokenHandler::Single(2)));
+    }
+
+    #[test]
+    fn merges_generation_config_eos_tokens_without_duplicates() {
+        let eos = eos_token_handler_with(
+            "merged",
+            json!({"eos_token_id": [128001, 128008]}),
+            Some(json!({"eos_token_id": [128001, 128009]})),
+        );
+        assert!(matches!(eos, EosTokenHandler::Multiple(ref ids) if ids == &[128001, 128008, 128009]));
+    }
+
+    #[test]
+    fn takes_eos_tokens_from_the_generation_config_alone() {
+        let eos = eos_token_handler_with("generation", json!({"eos_token_id": null}), Some(json!({"eos_token_id": 7})));
+        assert!(matches!(eos, EosTokenHandler::Single(7)));
+
+        let eos = eos_token_handler_with("none", json!({}), Some(json!({})));
+        assert!(matches!(eos, EosTokenHandler::None));
+    }
+
+    #[test]
+    fn extends_eos_tokens_with_end_of_turn_tokens() {
+        let eos = EosTokenHandler::Single(1).with_token(1);
+        assert!(matches!(eos, EosTokenHandler::Single(1)));
+
+        let eos = EosTokenHandler::Single(1).with_token(107).with_token(107);
+        assert!(matches!(eos, EosTokenHandler::Multiple(ref ids) if ids == &[1, 107]));
+        assert!(eos.is_eos_token(107));
+        assert!(!eos.is_eos_token(2));
+    }
+}
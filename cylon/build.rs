@@ -1,4 +1,11 @@
+use std::{env, path::PathBuf};
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("proto/cylon.proto")?;
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+
+    // The descriptor set is served through gRPC reflection
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("cylon_descriptor.bin"))
+        .compile_protos(&["proto/cylon.proto"], &["proto"])?;
     Ok(())
 }
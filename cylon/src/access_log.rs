@@ -0,0 +1,159 @@
+use cylon_inference_engine::{Completion, FinishReason};
+use std::time::{Duration, Instant};
+
+/// One structured audit record per job, emitted when the job reaches a final status
+#[derive(Debug, Clone)]
+pub struct AccessRecord {
+    pub job_id: String,
+    pub client_ip: String,
+    pub api_key_id: Option<String>,
+    pub model: String,
+    received_at: Instant,
+    started_at: Option<Instant>,
+}
+
+impl AccessRecord {
+    pub fn new(job_id: String, client_ip: String, api_key_id: Option<String>, model: String) -> Self {
+        AccessRecord {
+            job_id,
+            client_ip,
+            api_key_id,
+            model,
+            received_at: Instant::now(),
+            started_at: None,
+        }
+    }
+
+    /// Mark the point where the job left the queue and inference began
+    pub fn start(&mut self) {
+        self.started_at = Some(Instant::now());
+    }
+
+    /// Emit the record with the final status and, if inference ran, its measurements
+    pub fn finish(&self, status: &'static str, completion: Option<&Completion>) {
+        metrics::counter!("cylon_requests_total", "status" => status, "model" => self.model.clone()).increment(1);
+
+        let now = Instant::now();
+        let started_at = self.started_at.unwrap_or(now);
+        let queue_wait = started_at.duration_since(self.received_at);
+        let run_time = now.duration_since(started_at);
+
+        let stats = completion.map(|c| &c.stats);
+        let prompt_tokens = stats.map(|s| s.prompt_tokens).unwrap_or(0);
+        let completion_tokens = stats.map(|s| s.completion_tokens).unwrap_or(0);
+        let finish_reason = stats.map(|s| s.finish_reason);
+
+        tracing::info!(
+            target: "cylon::access",
+            job_id = %self.job_id,
+            client_ip = %self.client_ip,
+            api_key_id = self.api_key_id.as_deref().unwrap_or("-"),
+            model = %self.model,
+            prompt_tokens,
+            completion_tokens,
+            queue_wait_ms = millis(queue_wait),
+            run_time_ms = millis(run_time),
+            finish_reason = finish_reason.as_ref().map(FinishReason::to_string).as_deref().unwrap_or("-"),
+            status,
+            "access"
+        );
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cylon_inference_engine::GenerationStats;
+    use metrics_exporter_prometheus::PrometheusBuilder;
+    use serde_json::Value;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// The JSON access record `record.finish` logs
+    fn finish(record: &AccessRecord, status: &'static str, completion: Option<&Completion>) -> Value {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || record.finish(status, completion));
+
+        let output = captured.0.lock().unwrap().clone();
+        serde_json::from_slice(&output).unwrap()
+    }
+
+    #[test]
+    fn records_a_completed_job() {
+        let mut record = AccessRecord::new(String::from("job-1"), String::from("10.0.0.1"), Some(String::from("ci")), String::from("tiny"));
+        record.start();
+        let completion = Completion {
+            text: String::from("blue"),
+            stats: GenerationStats {
+                prompt_tokens: 12,
+                completion_tokens: 3,
+                finish_reason: FinishReason::Stop,
+                prefill_time: Duration::from_millis(5),
+                decode_time: Duration::from_millis(7),
+            },
+        };
+
+        let line = finish(&record, "OK", Some(&completion));
+        assert_eq!(line["target"], "cylon::access");
+        let fields = &line["fields"];
+        assert_eq!(fields["job_id"], "job-1");
+        assert_eq!(fields["client_ip"], "10.0.0.1");
+        assert_eq!(fields["api_key_id"], "ci");
+        assert_eq!(fields["model"], "tiny");
+        assert_eq!(fields["prompt_tokens"], 12);
+        assert_eq!(fields["completion_tokens"], 3);
+        assert_eq!(fields["finish_reason"], "stop");
+        assert_eq!(fields["status"], "OK");
+    }
+
+    #[test]
+    fn records_a_job_that_never_ran() {
+        let record = AccessRecord::new(String::from("job-2"), String::from("10.0.0.2"), None, String::from("tiny"));
+
+        let line = finish(&record, "CANCELLED", None);
+        let fields = &line["fields"];
+        assert_eq!(fields["api_key_id"], "-");
+        assert_eq!(fields["prompt_tokens"], 0);
+        assert_eq!(fields["completion_tokens"], 0);
+        assert_eq!(fields["run_time_ms"], 0);
+        assert_eq!(fields["finish_reason"], "-");
+        assert_eq!(fields["status"], "CANCELLED");
+    }
+
+    #[test]
+    fn counts_requests_by_status_and_model() {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let record = AccessRecord::new(String::from("job-3"), String::from("10.0.0.3"), None, String::from("tiny"));
+        metrics::with_local_recorder(&recorder, || {
+            record.finish("OK", None);
+            record.finish("OK", None);
+        });
+
+        let rendered = handle.render();
+        assert!(rendered.contains("cylon_requests_total{status=\"OK\",model=\"tiny\"} 2"), "{rendered}");
+    }
+}
@@ -0,0 +1,248 @@
+use std::sync::Arc;
+use std::time::Duration;
+use cylon_models::{create_model, estimate_model_size};
+use tonic::{Request, Response, Status};
+
+use crate::cylon_proto::cylon_admin_server::CylonAdmin;
+use crate::cylon_proto::{DrainReply, DrainRequest, SetLogLevelReply, SetLogLevelRequest};
+use crate::cylon_proto::{ExpireResultsRequest, Job, ListJobsReply, ListJobsRequest, PurgeResultsReply, PurgeResultsRequest};
+use crate::cylon_proto::{InferenceRunReply, ReloadModelReply, ReloadModelRequest, SetDefaultsReply, SetDefaultsRequest};
+use crate::observability::{build_log_filter, LogFilterHandle};
+use crate::prompt_queue::JobSummary;
+use crate::registry::{ModelRegistry, ModelSlot, SamplingOverrides};
+use crate::result_cache::ResultCache;
+use crate::shutdown::{Drainer, InFlight};
+use crate::sampling_settings;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+/// Operator-facing service for runtime administration
+#[derive(Debug)]
+pub struct Admin {
+    pub(crate) log_filter: LogFilterHandle,
+    pub(crate) drainer: Drainer,
+    pub(crate) models: Arc<ModelRegistry>,
+    pub(crate) results: Arc<ResultCache<String, InferenceRunReply>>,
+    pub(crate) in_flight: InFlight,
+}
+
+#[tonic::async_trait]
+impl CylonAdmin for Admin {
+    async fn set_log_level(
+        &self,
+        request: Request<SetLogLevelRequest>,
+    ) -> Result<Response<SetLogLevelReply>, Status> {
+        let directives = request.into_inner().directives;
+
+        let filter = build_log_filter(&directives)
+            .map_err(|e| Status::invalid_argument(format!("Invalid log filter directives: {}", e)))?;
+
+        let previous_directives = self.log_filter
+            .with_current(|filter| filter.to_string())
+            .map_err(|e| Status::internal(format!("Failed to read log filter: {}", e)))?;
+
+        self.log_filter
+            .reload(filter)
+            .map_err(|e| Status::internal(format!("Failed to reload log filter: {}", e)))?;
+
+        info!("Log filter changed from '{}' to '{}'", previous_directives, directives);
+
+        Ok(Response::new(SetLogLevelReply { previous_directives, directives }))
+    }
+
+    async fn drain(
+        &self,
+        request: Request<DrainRequest>,
+    ) -> Result<Response<DrainReply>, Status> {
+        let req = request.into_inner();
+        let grace_period = match req.grace_period_seconds {
+            0 => self.drainer.grace_period,
+            seconds => Duration::from_secs(seconds),
+        };
+
+        info!("Drain requested via admin API");
+        let summary = self.drainer.drain_with(grace_period, req.cancel_queued).await;
+
+        Ok(Response::new(DrainReply {
+            cancelled_jobs: summary.cancelled_jobs as u32,
+            unfinished_jobs: summary.unfinished_jobs as u32,
+        }))
+    }
+
+    async fn list_jobs(
+        &self,
+        _request: Request<ListJobsRequest>,
+    ) -> Result<Response<ListJobsReply>, Status> {
+        let mut jobs: Vec<Job> = self.in_flight
+            .jobs()
+            .into_iter()
+            .map(|job| job_info(job, "RUNNING"))
+            .collect();
+        for slot in self.models.iter() {
+            let queued = slot.queue.lock().await.jobs();
+            jobs.extend(queued.into_iter().map(|job| job_info(job, "QUEUED")));
+        }
+
+        Ok(Response::new(ListJobsReply { jobs }))
+    }
+
+    async fn purge_results(
+        &self,
+        request: Request<PurgeResultsRequest>,
+    ) -> Result<Response<PurgeResultsReply>, Status> {
+        let req = request.into_inner();
+
+        let purged = if req.all {
+            self.results.clear()
+        } else {
+            req.job_ids.iter().filter(|job_id| self.results.remove(job_id)).count()
+        };
+
+        info!("Purged {} results via admin API", purged);
+
+        Ok(Response::new(PurgeResultsReply { purged: purged as u32 }))
+    }
+
+    async fn expire_results(
+        &self,
+        request: Request<ExpireResultsRequest>,
+    ) -> Result<Response<PurgeResultsReply>, Status> {
+        let older_than = request.into_inner().older_than_seconds;
+        let age = chrono::Duration::seconds(i64::try_from(older_than).unwrap_or(i64::MAX));
+        let purged = self.results.expire_older_than(age);
+
+        info!("Expired {} results older than {}s via admin API", purged, older_than);
+
+        Ok(Response::new(PurgeResultsReply { purged: purged as u32 }))
+    }
+
+    async fn set_defaults(
+        &self,
+        request: Request<SetDefaultsRequest>,
+    ) -> Result<Response<SetDefaultsReply>, Status> {
+        let req = request.into_inner();
+        let slot = self.model(&req.model)?;
+
+        if req.max_tokens == Some(0) {
+            return Err(Status::invalid_argument("max_tokens must be greater than 0"));
+        }
+        let overrides = SamplingOverrides::from(&req);
+        overrides.validate()?;
+
+        // Waits for the running generation so it finishes with the settings it started with
+        let mut model = slot.model.lock().await;
+        let mut runtime = slot.runtime.write().unwrap();
+        if let Some(system_prompt) = req.system_prompt {
+            runtime.system_prompt = system_prompt;
+        }
+        if let Some(max_tokens) = req.max_tokens {
+            runtime.sample_len = max_tokens as usize;
+        }
+        runtime.overrides.merge(overrides);
+        let mut sampling = runtime.sampling.clone();
+        runtime.overrides.apply(&mut sampling);
+        // An unloaded model picks the overrides up when it is next loaded
+        if let Some(model) = model.as_mut() {
+            model.set_inference_config(sampling.clone());
+        }
+        runtime.sampling = sampling;
+
+        info!(
+            "Generation defaults for model {} changed via admin API: max_tokens={}, sampling={:?}",
+            slot.name, runtime.sample_len, runtime.sampling
+        );
+
+        Ok(Response::new(SetDefaultsReply {
+            system_prompt: runtime.system_prompt.clone(),
+            max_tokens: runtime.sample_len as u64,
+            sampling: Some(sampling_settings(&runtime.sampling)),
+            model: slot.name.clone(),
+        }))
+    }
+
+    /// Load the replacement, then drain the model's outstanding jobs and swap it in
+    ///
+    /// The current model keeps serving while the replacement loads, so it only
+    /// refuses requests during the drain, and a failed load leaves it untouched.
+    /// Peak memory is that of both models together, outside of the memory budget.
+    async fn reload_model(
+        &self,
+        request: Request<ReloadModelRequest>,
+    ) -> Result<Response<ReloadModelReply>, Status> {
+        let req = request.into_inner();
+        if req.model_path.is_empty() {
+            return Err(Status::invalid_argument("model_path is required"));
+        }
+        let slot = self.model(&req.model)?;
+
+        let _reload_lock = slot.reload_lock
+            .try_lock()
+            .map_err(|_| Status::failed_precondition(format!("Model {} is already being reloaded", slot.name)))?;
+
+        let mut new_config = slot.config.read().unwrap().clone();
+        new_config.model_path = req.model_path;
+        if !req.model_family.is_empty() {
+            new_config.model_family = req.model_family;
+        }
+
+        let grace_period = match req.grace_period_seconds {
+            0 => self.drainer.grace_period,
+            seconds => Duration::from_secs(seconds),
+        };
+
+        info!("Reloading model {} from {} via admin API", slot.name, new_config.model_path);
+        let (model, new_config, size) = tokio::task::spawn_blocking(move || {
+            let size = estimate_model_size(&new_config)?;
+            create_model(&new_config).map(|model| (model, new_config, size))
+        })
+        .await
+        .map_err(|e| Status::internal(format!("Load task failed: {}", e)))?
+        .map_err(|e| {
+            error!("Failed to load model {}, keeping the current one: {:#}", slot.name, e);
+            Status::failed_precondition(format!("Failed to load model: {:#}", e))
+        })?;
+
+        let reloading = slot.start_reloading();
+        let summary = self.drainer.drain_model(slot, grace_period).await;
+
+        let model_path = new_config.model_path.clone();
+        let mut current = slot.model.lock().await;
+        let old_weights = current.take();
+        // Applies the overrides set via SetDefaults over the new checkpoint's defaults
+        slot.install(&mut current, model, new_config, size);
+        drop(current);
+        drop(reloading);
+
+        info!("Model {} now serving from {}", slot.name, model_path);
+
+        // Freeing the weights can take a while, so keep it off the async workers
+        if let Err(e) = tokio::task::spawn_blocking(move || drop(old_weights)).await {
+            error!("Failed to free the previous weights of model {}: {}", slot.name, e);
+        }
+
+        Ok(Response::new(ReloadModelReply {
+            model: Some(slot.describe()),
+            cancelled_jobs: summary.cancelled_jobs as u32,
+        }))
+    }
+}
+
+impl Admin {
+    #[allow(clippy::result_large_err)]
+    fn model(&self, name: &str) -> Result<&Arc<ModelSlot>, Status> {
+        self.models
+            .get(name)
+            .ok_or_else(|| Status::not_found(format!("Model {} not found", name)))
+    }
+}
+
+fn job_info(job: JobSummary, state: &str) -> Job {
+    Job {
+        job_id: job.job_id,
+        state: state.to_string(),
+        owner: job.owner.to_string(),
+        model: job.model,
+        age_ms: job.received_at.elapsed().as_millis() as u64,
+    }
+}
@@ -4,14 +4,24 @@ use uuid::Uuid;
 
 use crate::cylon_proto::cylon_api_server::CylonApi;
 use crate::cylon_proto::{InferenceRunReply, InferenceRunRequest, InferenceStatusRequest, InferenceStatusReply, InferenceResultRequest, InferenceResultResponse, Message};
-use crate::queue_processor::QueueProcessor;
-use crate::Cylon;
+use crate::cylon_proto::{ListModelsRequest, ListModelsReply, GetModelInfoRequest, ModelInfo, GetServerInfoRequest, ServerInfo};
+use crate::access_log::AccessRecord;
+use crate::auth::{principal, Caller};
+use crate::health::ServingState;
+use crate::observability::extract_trace_context;
+use crate::prompt_queue::JobSummary;
+use crate::queue_processor::{QueueHandoff, QueueProcessor};
+use crate::registry::SamplingOverrides;
+use crate::{sampling_settings, Cylon};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 #[allow(unused_imports)]
 use tracing::{info, debug, error, warn};
 
 #[tonic::async_trait]
 impl CylonApi for Cylon {
+    #[tracing::instrument(skip_all, fields(job_id, client_ip, client_id))]
     async fn inference_run(
         &self,
         request: Request<InferenceRunRequest>,
@@ -20,25 +30,73 @@ impl CylonApi for Cylon {
         let client_ip = request.remote_addr()
             .map(|addr| addr.to_string())
             .unwrap_or_else(|| "unknown".to_string());
-        
+        let job_id = Uuid::new_v4().to_string();
+        let api_key_id = principal(&request).map(|p| p.id.clone());
+
+        let span = Span::current();
+        span.set_parent(extract_trace_context(request.metadata()));
+        span.record("job_id", job_id.as_str());
+        span.record("client_ip", client_ip.as_str());
+        if let Some(api_key_id) = &api_key_id {
+            span.record("client_id", api_key_id.as_str());
+        }
+
         info!("Got a request for inference from client IP: {}", client_ip);
 
-        debug!("Request: {:?}", request);
+        match self.health.state() {
+            ServingState::Draining => return Err(Status::unavailable("Server is draining and not accepting new requests")),
+            ServingState::Loading => return Err(Status::unavailable("Model is loading")),
+            _ => {}
+        }
+
+        let slot = self.models
+            .get(&request.get_ref().model)
+            .ok_or_else(|| Status::not_found(format!("Model {} not found", request.get_ref().model)))?
+            .clone();
+        let model_name = slot.name.clone();
+
+        if let Some(principal) = principal(&request)
+            && !principal.may_use_model(&model_name)
+        {
+            return Err(Status::permission_denied(format!("API key {} may not use model {}", principal.id, model_name)));
+        }
+
+        if slot.is_reloading() {
+            return Err(Status::unavailable(format!("Model {} is reloading", model_name)));
+        }
+
+        if request.get_ref().max_tokens == Some(0) {
+            return Err(Status::invalid_argument("max_tokens must be greater than 0"));
+        }
+        SamplingOverrides::from(request.get_ref()).validate()?;
+
+        let job = JobSummary {
+            job_id: job_id.clone(),
+            owner: Caller::from_request(&request).owner,
+            model: model_name.clone(),
+            received_at: std::time::Instant::now(),
+        };
 
         let req = request.into_inner();
-        let job_id = Uuid::new_v4().to_string();
+        let mut access = AccessRecord::new(job_id.clone(), client_ip, api_key_id, model_name);
+
+        debug!("Request: {}", self.redactor.messages(&req.messages));
 
         // If queue is disabled, process all requests immediately and sequentially
         if self.queue_disabled {
             debug!("Queue disabled - processing request immediately and sequentially");
             
             // Wait for any current processing to complete, then process this request
-            let _processing_guard = self.processing.lock().await;
+            let _in_flight = self.in_flight.enter(job);
+            let _processing_guard = slot.processing.lock().await;
             
-            let response = self.process_inference_request(req).await?;
+            access.start();
+            let completion = self.process_inference_request(&slot, req).await
+                .inspect_err(|_| access.finish("ERROR", None))?;
+            access.finish("OK", Some(&completion));
             
             let reply = InferenceRunReply { 
-                response: Some(Message{ role: "assistant".to_string(), content: response }), 
+                response: Some(Message{ role: "assistant".to_string(), content: completion.text }), 
                 status: "OK".to_string(), 
                 uuid: job_id 
             };
@@ -47,7 +105,7 @@ impl CylonApi for Cylon {
         }
         
         // Check if we're currently processing a request
-        let mut processing = self.processing.lock().await;
+        let mut processing = slot.processing.lock().await;
         let is_processing = *processing;
         debug!("Processing flag is: {}", is_processing);
         
@@ -55,48 +113,46 @@ impl CylonApi for Cylon {
             // No inference running - process this request immediately
             *processing = true;
             drop(processing); // Release the processing lock
+
+            // Process queued items after this one, however it ends; an empty queue resets the flag
+            let _handoff = QueueHandoff::new(QueueProcessor {
+                models: Arc::clone(&self.models),
+                slot: Arc::clone(&slot),
+                results: Arc::clone(&self.results),
+                redactor: Arc::clone(&self.redactor),
+                in_flight: self.in_flight.clone(),
+            });
+
+            let in_flight = self.in_flight.enter(job);
             
-            let response = self.process_inference_request(req).await?;
+            access.start();
+            let completion = self.process_inference_request(&slot, req).await
+                .inspect_err(|_| access.finish("ERROR", None))?;
+            access.finish("OK", Some(&completion));
 
             let reply = InferenceRunReply { 
-                response: Some(Message{ role: "assistant".to_string(), content: response }), 
+                response: Some(Message{ role: "assistant".to_string(), content: completion.text }), 
                 status: "OK".to_string(), 
                 uuid: job_id 
             };
-
-            // Spawn a task to process queued items after this one completes
-            let queue = Arc::clone(&self.queue);
-            let processing = Arc::clone(&self.processing);
-            let results = Arc::clone(&self.results);
-            let model = Arc::clone(&self.model);
-            let system_prompt = self.system_prompt.clone();
-            let sample_len = self.sample_len;
-            
-            tokio::spawn(async move {
-                // Create a temporary Cylon-like struct for queue processing
-                let processor = QueueProcessor {
-                    queue,
-                    processing,
-                    results,
-                    model,
-                    system_prompt,
-                    sample_len,
-                };
-                processor.process_queue().await;
-            });
+            drop(in_flight);
 
             Ok(Response::new(reply))
         } else {
             // Currently processing - enqueue this request and return QUEUED status
             drop(processing); // Release the processing lock
             
-            let mut queue = self.queue.lock().await;
-            queue.enqueue(job_id.clone(), req).await
-                .map_err(|e| Status::internal(format!("Failed to enqueue request: {}", e)))?;
+            let mut queue = slot.queue.lock().await;
+            let owner = job.owner.clone();
+            queue.enqueue(job, req, access.clone()).await
+                .map_err(|e| {
+                    access.finish("REJECTED", None);
+                    Status::internal(format!("Failed to enqueue request: {}", e))
+                })?;
             drop(queue);
             
             // Store the job as QUEUED status using DashMap
-            self.results.insert(job_id.clone(), InferenceRunReply {
+            self.results.insert(job_id.clone(), owner, InferenceRunReply {
                 response: None,
                 status: "QUEUED".to_string(),
                 uuid: job_id.clone(),
@@ -116,29 +172,90 @@ impl CylonApi for Cylon {
         &self,
         request: Request<InferenceStatusRequest>,
     ) -> Result<Response<InferenceStatusReply>, Status> {
-        let req = request.into_inner();
-        let job_id = req.uuid;
-        
-        if let Some(result) = self.results.get(&job_id) {
-            Ok(Response::new(InferenceStatusReply { status: result.status.clone() }))
-        } else {
-            Err(Status::not_found(format!("Job ID {} not found", job_id)))
-        }
+        let caller = Caller::from_request(&request);
+        let job_id = request.into_inner().uuid;
+        let result = self.owned_result(&caller, &job_id)?;
+
+        Ok(Response::new(InferenceStatusReply { status: result.status }))
     }
 
     async fn inference_result(
         &self,
         request: Request<InferenceResultRequest>,
     ) -> Result<Response<InferenceResultResponse>, Status> {
-        let req = request.into_inner();
-        let job_id = req.uuid;
-        
-        if let Some(result) = self.results.get(&job_id) {
-            Ok(Response::new(InferenceResultResponse { 
-                response: result.response.clone() 
-            }))
-        } else {
-            Err(Status::not_found(format!("Job ID {} not found", job_id)))
+        let caller = Caller::from_request(&request);
+        let job_id = request.into_inner().uuid;
+        let result = self.owned_result(&caller, &job_id)?;
+
+        Ok(Response::new(InferenceResultResponse { response: result.response }))
+    }
+
+    async fn list_models(
+        &self,
+        request: Request<ListModelsRequest>,
+    ) -> Result<Response<ListModelsReply>, Status> {
+        let models = self.models
+            .iter()
+            .map(|slot| slot.name.clone())
+            .filter(|model| principal(&request).is_none_or(|p| p.may_use_model(model)))
+            .collect();
+
+        Ok(Response::new(ListModelsReply { models }))
+    }
+
+    async fn get_model_info(
+        &self,
+        request: Request<GetModelInfoRequest>,
+    ) -> Result<Response<ModelInfo>, Status> {
+        let caller = principal(&request).cloned();
+        let mut model = request.into_inner().model;
+        if model.is_empty() {
+            model = self.models.default().name.clone();
         }
+
+        if let Some(caller) = caller
+            && !caller.may_use_model(&model)
+        {
+            return Err(Status::permission_denied(format!("API key {} may not use model {}", caller.id, model)));
+        }
+
+        let slot = self.models
+            .get(&model)
+            .ok_or_else(|| Status::not_found(format!("Model {} not found", model)))?;
+
+        Ok(Response::new(slot.describe()))
+    }
+
+    async fn get_server_info(
+        &self,
+        _request: Request<GetServerInfoRequest>,
+    ) -> Result<Response<ServerInfo>, Status> {
+        let default_model = self.models.default();
+        let runtime = default_model.runtime.read().unwrap();
+
+        Ok(Response::new(ServerInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            default_model: default_model.name.clone(),
+            max_tokens: runtime.sample_len as u64,
+            sampling: Some(sampling_settings(&runtime.sampling)),
+            queue: Some(self.queue_settings.clone()),
+        }))
+    }
+}
+
+impl Cylon {
+    /// Fetch a job's result, refusing callers other than its submitter unless they are an admin
+    #[allow(clippy::result_large_err)]
+    fn owned_result(&self, caller: &Caller, job_id: &str) -> Result<InferenceRunReply, Status> {
+        let (result, owner) = self.results
+            .get(&job_id.to_string())
+            .ok_or_else(|| Status::not_found(format!("Job ID {} not found", job_id)))?;
+
+        if !caller.may_access(&owner) {
+            warn!("Denied {} access to job {} owned by {}", caller.owner, job_id, owner);
+            return Err(Status::permission_denied(format!("Job ID {} belongs to another principal", job_id)));
+        }
+
+        Ok(result)
     }
-}
\ No newline at end of file
+}
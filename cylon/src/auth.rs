@@ -0,0 +1,416 @@
+use anyhow::{bail, Context, Result};
+use cylon_config::CylonConfig;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tonic::service::Interceptor;
+use tonic::transport::server::UdsConnectInfo;
+use tonic::{Request, Status};
+use crate::tls::common_name;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+/// One entry of the API keys file
+///
+/// Keys are stored as the hex SHA-256 of the bearer token, e.g. `printf %s "$KEY" | sha256sum`.
+#[derive(Debug, Deserialize)]
+struct ApiKeyEntry {
+    id: String,
+    sha256: String,
+    /// Models this key may use; any model when empty
+    #[serde(default)]
+    models: Vec<String>,
+    /// Grants access to the admin service
+    #[serde(default)]
+    admin: bool,
+}
+
+/// The authenticated caller of a request, attached to its extensions by [`ApiKeyAuth`]
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub id: String,
+    pub models: Vec<String>,
+    pub admin: bool,
+}
+
+impl Principal {
+    pub fn may_use_model(&self, model: &str) -> bool {
+        self.models.is_empty() || self.models.iter().any(|m| m == model)
+    }
+}
+
+/// Validates `authorization: Bearer <key>` metadata against the hashed keys file
+///
+/// Without a keys file every API request is let through with no principal attached.
+#[derive(Debug, Clone)]
+pub struct ApiKeyAuth {
+    principals: Option<Arc<HashMap<String, Principal>>>,
+    /// Admit admin requests when there is no keys file to authenticate them against
+    admin_allow_unauthenticated: bool,
+}
+
+impl ApiKeyAuth {
+    pub fn from_config(config: &CylonConfig) -> Result<Self> {
+        let principals = match &config.api_keys_file {
+            Some(path) => Some(Arc::new(Self::load_keys(path)?)),
+            None if config.admin_allow_unauthenticated => {
+                warn!("No API keys file configured, requests including admin requests are not authenticated");
+                None
+            }
+            None => {
+                warn!("No API keys file configured, requests are not authenticated and the admin service is disabled");
+                None
+            }
+        };
+
+        Ok(ApiKeyAuth {
+            principals,
+            admin_allow_unauthenticated: config.admin_allow_unauthenticated,
+        })
+    }
+
+    fn load_keys(path: &str) -> Result<HashMap<String, Principal>> {
+        let path = Path::new(path);
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("API keys file not found: {}", path.display()))?;
+        let entries: Vec<ApiKeyEntry> = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to deserialize API keys file: {}", path.display()))?;
+
+        let mut principals = HashMap::new();
+        for entry in entries {
+            let hash = entry.sha256.trim().to_ascii_lowercase();
+            if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                bail!("API key {} does not have a valid sha256 hash", entry.id);
+            }
+
+            let principal = Principal {
+                id: entry.id,
+                models: entry.models,
+                admin: entry.admin,
+            };
+            if let Some(existing) = principals.insert(hash, principal) {
+                bail!("API key {} has the same hash as another key", existing.id);
+            }
+        }
+
+        info!("Loaded {} API keys from {}", principals.len(), path.display());
+
+        Ok(principals)
+    }
+
+    // Status is what tonic interceptors must return, boxing it here would only be undone by the callers
+    #[allow(clippy::result_large_err)]
+    fn authenticate<T>(&self, request: &Request<T>) -> Result<Option<Principal>, Status> {
+        let Some(principals) = &self.principals else {
+            return Ok(None);
+        };
+
+        let header = request
+            .metadata()
+            .get("authorization")
+            .ok_or_else(|| Status::unauthenticated("Missing API key"))?
+            .to_str()
+            .map_err(|_| Status::unauthenticated("Invalid authorization header"))?;
+
+        let key = header
+            .strip_prefix("Bearer ")
+            .ok_or_else(|| Status::unauthenticated("Authorization header must use the Bearer scheme"))?;
+
+        let hash = format!("{:x}", Sha256::digest(key.trim().as_bytes()));
+        principals
+            .get(&hash)
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| Status::unauthenticated("Invalid API key"))
+    }
+
+    /// Interceptor for the admin service, which additionally requires an admin key
+    pub fn admin(&self) -> AdminAuth {
+        AdminAuth(self.clone())
+    }
+}
+
+impl Interceptor for ApiKeyAuth {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(principal) = self.authenticate(&request)? {
+            request.extensions_mut().insert(principal);
+        }
+        Ok(request)
+    }
+}
+
+/// Admits only principals with the `admin` permission
+///
+/// Without a keys file every request is refused unless `admin_allow_unauthenticated` is set.
+#[derive(Debug, Clone)]
+pub struct AdminAuth(ApiKeyAuth);
+
+impl Interceptor for AdminAuth {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        match self.0.authenticate(&request)? {
+            Some(principal) if !principal.admin => {
+                Err(Status::permission_denied(format!("API key {} may not use the admin service", principal.id)))
+            }
+            Some(principal) => {
+                request.extensions_mut().insert(principal);
+                Ok(request)
+            }
+            None if self.0.admin_allow_unauthenticated => Ok(request),
+            None => Err(Status::unauthenticated(
+                "The admin service requires API keys; set api_keys_file or admin_allow_unauthenticated",
+            )),
+        }
+    }
+}
+
+/// The principal attached to a request, or `None` when API keys are not configured
+pub fn principal<T>(request: &Request<T>) -> Option<&Principal> {
+    request.extensions().get::<Principal>()
+}
+
+/// Identity a job is recorded under, so that only its submitter can look it up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Owner {
+    ApiKey(String),
+    /// Common name of a verified client certificate
+    Certificate(String),
+    /// Fallback for unauthenticated plaintext clients
+    Address(IpAddr),
+    /// User id of a Unix socket peer
+    UnixUser(u32),
+    /// Matches no other caller, so jobs submitted without an identity cannot be looked up
+    Unknown,
+}
+
+impl fmt::Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Owner::ApiKey(id) => write!(f, "key:{}", id),
+            Owner::Certificate(cn) => write!(f, "cn:{}", cn),
+            Owner::Address(ip) => write!(f, "ip:{}", ip),
+            Owner::UnixUser(uid) => write!(f, "uid:{}", uid),
+            Owner::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Who is making a request, for deciding which jobs they may see
+#[derive(Debug, Clone)]
+pub struct Caller {
+    pub owner: Owner,
+    /// Admin keys may look up any job
+    pub admin: bool,
+}
+
+impl Caller {
+    /// Identify the caller by API key, then client certificate, then client address
+    /// or Unix socket peer credentials
+    pub fn from_request<T>(request: &Request<T>) -> Self {
+        if let Some(principal) = principal(request) {
+            return Caller {
+                owner: Owner::ApiKey(principal.id.clone()),
+                admin: principal.admin,
+            };
+        }
+
+        let cn = request
+            .peer_certs()
+            .and_then(|certs| certs.first().and_then(|cert| common_name(cert)));
+
+        let uid = request
+            .extensions()
+            .get::<UdsConnectInfo>()
+            .and_then(|info| info.peer_cred)
+            .map(|cred| cred.uid());
+
+        let owner = match (cn, request.remote_addr(), uid) {
+            (Some(cn), _, _) => Owner::Certificate(cn),
+            (None, Some(addr), _) => Owner::Address(addr.ip()),
+            (None, None, Some(uid)) => Owner::UnixUser(uid),
+            (None, None, None) => Owner::Unknown,
+        };
+
+        Caller { owner, admin: false }
+    }
+
+    pub fn may_access(&self, owner: &Owner) -> bool {
+        self.admin || (self.owner != Owner::Unknown && self.owner == *owner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::transport::server::TcpConnectInfo;
+
+    fn sha256(key: &str) -> String {
+        format!("{:x}", Sha256::digest(key.as_bytes()))
+    }
+
+    /// Write `content` to a keys file unique to `test` and load it
+    fn load_keys(test: &str, content: &str) -> Result<HashMap<String, Principal>> {
+        let path = std::env::temp_dir().join(format!("cylon-{}-{}.yaml", test, std::process::id()));
+        fs::write(&path, content).unwrap();
+        let keys = ApiKeyAuth::load_keys(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        keys
+    }
+
+    fn auth(test: &str) -> ApiKeyAuth {
+        let content = format!(
+            "- id: ci\n  sha256: {}\n  models: [tiny]\n- id: ops\n  sha256: {}\n  admin: true\n",
+            sha256("ci-key"),
+            sha256("ops-key").to_uppercase(),
+        );
+        ApiKeyAuth {
+            principals: Some(Arc::new(load_keys(test, &content).unwrap())),
+            admin_allow_unauthenticated: false,
+        }
+    }
+
+    fn request(authorization: Option<&str>) -> Request<()> {
+        let mut request = Request::new(());
+        if let Some(authorization) = authorization {
+            request.metadata_mut().insert("authorization", authorization.parse().unwrap());
+        }
+        request
+    }
+
+    #[test]
+    fn authenticates_keys_by_hash() {
+        let auth = auth("by-hash");
+
+        let ci = auth.authenticate(&request(Some("Bearer ci-key"))).unwrap().unwrap();
+        assert_eq!(ci.id, "ci");
+        assert!(!ci.admin);
+
+        // Hashes are compared in lower case and the key is trimmed
+        let ops = auth.authenticate(&request(Some("Bearer ops-key "))).unwrap().unwrap();
+        assert_eq!(ops.id, "ops");
+        assert!(ops.admin);
+    }
+
+    #[test]
+    fn rejects_missing_and_unknown_keys() {
+        let auth = auth("unknown");
+
+        for authorization in [None, Some("Bearer wrong-key"), Some("Basic ci-key"), Some("ci-key")] {
+            let status = auth.authenticate(&request(authorization)).unwrap_err();
+            assert_eq!(status.code(), tonic::Code::Unauthenticated, "{authorization:?}");
+        }
+    }
+
+    #[test]
+    fn lets_everything_through_without_a_keys_file() {
+        let auth = ApiKeyAuth { principals: None, admin_allow_unauthenticated: false };
+        assert!(auth.authenticate(&request(None)).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_and_duplicate_hashes() {
+        let err = load_keys("invalid", "- id: ci\n  sha256: not-a-hash\n").unwrap_err();
+        assert!(err.to_string().contains("API key ci does not have a valid sha256 hash"));
+
+        let hash = sha256("ci-key");
+        let content = format!("- id: ci\n  sha256: {hash}\n- id: copy\n  sha256: {hash}\n");
+        let err = load_keys("duplicate", &content).unwrap_err();
+        assert!(err.to_string().contains("API key ci has the same hash as another key"));
+    }
+
+    #[test]
+    fn limits_keys_to_their_models() {
+        let auth = auth("models");
+        let ci = auth.authenticate(&request(Some("Bearer ci-key"))).unwrap().unwrap();
+        assert!(ci.may_use_model("tiny"));
+        assert!(!ci.may_use_model("large"));
+
+        let ops = auth.authenticate(&request(Some("Bearer ops-key"))).unwrap().unwrap();
+        assert!(ops.may_use_model("large"));
+    }
+
+    #[test]
+    fn admin_service_requires_an_admin_key() {
+        let auth = auth("admin");
+
+        let status = auth.admin().call(request(Some("Bearer ci-key"))).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+        let admitted = auth.admin().call(request(Some("Bearer ops-key"))).unwrap();
+        assert_eq!(principal(&admitted).unwrap().id, "ops");
+    }
+
+    #[test]
+    fn admin_service_without_keys_follows_admin_allow_unauthenticated() {
+        let closed = ApiKeyAuth { principals: None, admin_allow_unauthenticated: false };
+        let status = closed.admin().call(request(None)).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let open = ApiKeyAuth { principals: None, admin_allow_unauthenticated: true };
+        assert!(open.admin().call(request(None)).is_ok());
+    }
+
+    #[test]
+    fn identifies_callers_by_key_then_address() {
+        let mut auth = auth("caller");
+        let mut request = auth.call(request(Some("Bearer ops-key"))).unwrap();
+        request.extensions_mut().insert(TcpConnectInfo {
+            local_addr: None,
+            remote_addr: Some("10.0.0.7:4000".parse().unwrap()),
+        });
+        let caller = Caller::from_request(&request);
+        assert_eq!(caller.owner, Owner::ApiKey(String::from("ops")));
+        assert!(caller.admin);
+
+        let mut request = Request::new(());
+        request.extensions_mut().insert(TcpConnectInfo {
+            local_addr: None,
+            remote_addr: Some("10.0.0.7:4000".parse().unwrap()),
+        });
+        let caller = Caller::from_request(&request);
+        assert_eq!(caller.owner, Owner::Address("10.0.0.7".parse().unwrap()));
+        assert!(!caller.admin);
+
+        assert_eq!(Caller::from_request(&Request::new(())).owner, Owner::Unknown);
+    }
+
+    #[test]
+    fn callers_may_only_access_their_own_jobs() {
+        let ci = Caller { owner: Owner::ApiKey(String::from("ci")), admin: false };
+        assert!(ci.may_access(&Owner::ApiKey(String::from("ci"))));
+        assert!(!ci.may_access(&Owner::ApiKey(String::from("other"))));
+        assert!(!ci.may_access(&Owner::Certificate(String::from("ci"))));
+
+        let uid = Caller { owner: Owner::UnixUser(1000), admin: false };
+        assert!(uid.may_access(&Owner::UnixUser(1000)));
+        assert!(!uid.may_access(&Owner::UnixUser(0)));
+    }
+
+    #[test]
+    fn unknown_callers_match_no_job() {
+        let unknown = Caller { owner: Owner::Unknown, admin: false };
+        assert!(!unknown.may_access(&Owner::Unknown));
+        assert!(!unknown.may_access(&Owner::Address("10.0.0.7".parse().unwrap())));
+    }
+
+    #[test]
+    fn admins_may_access_any_job() {
+        let admin = Caller { owner: Owner::ApiKey(String::from("ops")), admin: true };
+        assert!(admin.may_access(&Owner::ApiKey(String::from("ci"))));
+        assert!(admin.may_access(&Owner::Unknown));
+    }
+
+    #[test]
+    fn displays_owners_with_their_kind() {
+        assert_eq!(Owner::ApiKey(String::from("ci")).to_string(), "key:ci");
+        assert_eq!(Owner::Certificate(String::from("client")).to_string(), "cn:client");
+        assert_eq!(Owner::Address("10.0.0.7".parse().unwrap()).to_string(), "ip:10.0.0.7");
+        assert_eq!(Owner::UnixUser(1000).to_string(), "uid:1000");
+        assert_eq!(Owner::Unknown.to_string(), "unknown");
+    }
+}
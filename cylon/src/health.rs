@@ -0,0 +1,131 @@
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tonic::server::NamedService;
+use tonic_health::pb::health_server::{Health as HealthService, HealthServer};
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+
+use crate::cylon_proto::cylon_api_server::CylonApiServer;
+use crate::Cylon;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+/// Whether the engine can take inference requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServingState {
+    /// Model weights are still loading
+    Loading,
+    /// Accepting and processing requests
+    Serving,
+    /// Serving, but a model's prompt queue is full
+    Degraded,
+    /// No longer admitting new requests
+    Draining,
+}
+
+impl std::fmt::Display for ServingState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServingState::Loading => write!(f, "loading"),
+            ServingState::Serving => write!(f, "serving"),
+            ServingState::Degraded => write!(f, "degraded"),
+            ServingState::Draining => write!(f, "draining"),
+        }
+    }
+}
+
+/// Tracks serving state for the gRPC health service and the HTTP probes
+///
+/// The overall server status ("") is SERVING once the model is loaded and until
+/// draining starts. The `cylon.CylonApi` status additionally reports NOT_SERVING
+/// while any model's queue is saturated, so it can be used for readiness.
+#[derive(Debug)]
+pub struct Health {
+    reporter: HealthReporter,
+    loaded: AtomicBool,
+    draining: AtomicBool,
+    /// Prompt queue depth per model
+    queue_depths: DashMap<String, usize>,
+    queue_capacity: usize,
+}
+
+impl Health {
+    pub async fn new(queue_capacity: usize) -> (Arc<Self>, HealthServer<impl HealthService>) {
+        let (reporter, server) = tonic_health::server::health_reporter();
+
+        let health = Arc::new(Health {
+            reporter,
+            loaded: AtomicBool::new(false),
+            draining: AtomicBool::new(false),
+            queue_depths: DashMap::new(),
+            queue_capacity,
+        });
+        health.publish().await;
+
+        (health, server)
+    }
+
+    pub fn state(&self) -> ServingState {
+        if self.draining.load(Ordering::SeqCst) {
+            ServingState::Draining
+        } else if !self.loaded.load(Ordering::SeqCst) {
+            ServingState::Loading
+        } else if self.is_saturated() {
+            ServingState::Degraded
+        } else {
+            ServingState::Serving
+        }
+    }
+
+    /// Mark the model as loaded and ready to serve
+    pub async fn set_loaded(&self) {
+        self.loaded.store(true, Ordering::SeqCst);
+        self.publish().await;
+    }
+
+    /// Stop reporting as ready so load balancers move traffic elsewhere
+    pub async fn set_draining(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+        self.publish().await;
+    }
+
+    pub async fn set_queue_depth(&self, model: &str, depth: usize) {
+        let was_saturated = self.is_saturated();
+        self.queue_depths.insert(model.to_string(), depth);
+        let is_saturated = self.is_saturated();
+
+        if was_saturated != is_saturated {
+            if is_saturated {
+                warn!("Prompt queue for model {} saturated ({} requests), reporting degraded", model, depth);
+            }
+            self.publish().await;
+        }
+    }
+
+    fn is_saturated(&self) -> bool {
+        self.queue_depths.iter().any(|depth| *depth.value() >= self.queue_capacity)
+    }
+
+    async fn publish(&self) {
+        let state = self.state();
+
+        let server_status = match state {
+            ServingState::Serving | ServingState::Degraded => ServingStatus::Serving,
+            ServingState::Loading | ServingState::Draining => ServingStatus::NotServing,
+        };
+        let api_status = match state {
+            ServingState::Serving => ServingStatus::Serving,
+            _ => ServingStatus::NotServing,
+        };
+
+        let mut reporter = self.reporter.clone();
+        reporter.set_service_status("", server_status).await;
+        reporter
+            .set_service_status(<CylonApiServer<Cylon> as NamedService>::NAME, api_status)
+            .await;
+
+        debug!("Health state: {}", state);
+    }
+}
@@ -0,0 +1,58 @@
+use axum::{extract::State, http::StatusCode, routing::get, Router};
+use metrics_exporter_prometheus::PrometheusHandle;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::health::{Health, ServingState};
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+/// Shared state for the HTTP listener
+#[derive(Clone)]
+pub struct HttpState {
+    metrics: PrometheusHandle,
+    health: Arc<Health>,
+}
+
+impl HttpState {
+    pub fn new(metrics: PrometheusHandle, health: Arc<Health>) -> Self {
+        HttpState { metrics, health }
+    }
+}
+
+async fn metrics_handler(State(state): State<HttpState>) -> String {
+    state.metrics.render()
+}
+
+/// Liveness: the process is up and able to answer
+async fn healthz_handler() -> &'static str {
+    "ok"
+}
+
+/// Readiness: the model is loaded, the queue has room and we are not draining
+async fn readyz_handler(State(state): State<HttpState>) -> (StatusCode, String) {
+    let serving_state = state.health.state();
+    let status = match serving_state {
+        ServingState::Serving => StatusCode::OK,
+        _ => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    (status, serving_state.to_string())
+}
+
+/// Serve the HTTP endpoints (`/metrics`, `/healthz`, `/readyz`) until the listener fails
+pub async fn serve(addr: SocketAddr, state: HttpState) -> anyhow::Result<()> {
+    let app = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("HTTP server listening: {}", addr);
+
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
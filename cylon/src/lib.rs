@@ -1,36 +1,66 @@
 pub mod cylon_proto {
     tonic::include_proto!("cylon");
+
+    /// Encoded descriptors for the cylon services, served through gRPC reflection
+    pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("cylon_descriptor");
 }
 
+mod access_log;
+pub mod auth;
 mod prompt_queue;
 mod result_cache;
 mod queue_processor;
+mod registry;
 mod api;
+mod admin;
+mod redaction;
+pub mod shutdown;
+pub mod health;
+pub mod http;
+pub mod observability;
+pub mod tls;
 
 use anyhow::Result;
 use cylon_config::CylonConfig;
-use cylon_proto::{InferenceRunRequest, InferenceRunReply};
+use cylon_proto::{InferenceRunRequest, InferenceRunReply, QueueSettings, SamplingSettings};
 use serde::{Deserialize, Serialize};
-use serde_json;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use tonic::Status;
-use cylon_models::{create_model};
-use prompt_queue::PromptQueue;
+use cylon_inference_engine::{Completion, InferenceConfig};
+use cylon_models::chat_template::TemplateError;
+pub use admin::Admin;
+use health::Health;
+use observability::LogFilterHandle;
+use redaction::Redactor;
+use registry::{ModelRegistry, ModelSlot, SamplingOverrides};
 use result_cache::ResultCache;
+use shutdown::{Drainer, InFlight};
+use std::time::Duration;
 
 #[allow(unused_imports)]
 use tracing::{info, debug, error, warn};
 
 #[derive(Debug)]
 pub struct Cylon {
-    model: Arc<Mutex<Box<dyn cylon_inference_engine::TextGenerator>>>,
-    system_prompt: String,
-    sample_len: usize,
-    queue: Arc<Mutex<PromptQueue>>,
-    processing: Arc<Mutex<bool>>,
+    models: Arc<ModelRegistry>,
+    queue_settings: QueueSettings,
     results: Arc<ResultCache<String, InferenceRunReply>>,
     queue_disabled: bool,
+    redactor: Arc<Redactor>,
+    health: Arc<Health>,
+    in_flight: InFlight,
+    drainer: Drainer,
+}
+
+pub(crate) fn sampling_settings(config: &InferenceConfig) -> SamplingSettings {
+    SamplingSettings {
+        temperature: config.temperature,
+        top_p: config.top_p,
+        top_k: config.top_k.map(|k| k as u64),
+        repeat_penalty: config.repeat_penalty,
+        repeat_last_n: config.repeat_last_n as u64,
+        seed: config.seed,
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -40,73 +70,192 @@ pub struct Prompt {
 }
 
 impl Cylon {
-    pub fn new(config: &CylonConfig) -> anyhow::Result<Self> {
-        let model = Arc::new(Mutex::new(create_model(config)?));
-        
-        let system_prompt = Prompt {
-            role: String::from("system"),
-            content: config.system_prompt.clone(),
+    pub fn new(config: &CylonConfig, health: Arc<Health>) -> anyhow::Result<Self> {
+        let models = Arc::new(ModelRegistry::new(config, &health)?);
+
+        let queue_settings = QueueSettings {
+            disabled: config.queue_disabled,
+            r#type: config.queue_type.to_string(),
+            buffer_size: config.queue_buffer_size as u64,
+            result_ttl_seconds: config.result_cache_ttl,
         };
-        let system_prompt = serde_json::to_string(&system_prompt)?;
 
-        let queue = Arc::new(Mutex::new(PromptQueue::new(config.queue_buffer_size)));
-        let processing = Arc::new(Mutex::new(false));
         let results = Arc::new(ResultCache::new(config.result_cache_ttl));
+        let redactor = Arc::new(Redactor::new(config.log_content.clone(), &config.log_redaction_patterns)?);
+
+        let in_flight = InFlight::default();
+        let drainer = Drainer {
+            health: Arc::clone(&health),
+            models: Arc::clone(&models),
+            results: Arc::clone(&results),
+            in_flight: in_flight.clone(),
+            grace_period: Duration::from_secs(config.shutdown_grace_period),
+            cancel_queued: config.shutdown_cancel_queued,
+        };
+
+        ModelRegistry::start_idle_unloader(Arc::clone(&models));
 
         // Start background cleanup task for expired results (every 5 minutes)
         ResultCache::start_cleanup_task(Arc::clone(&results), 300);
 
         Ok(Cylon {
-            model,
-            system_prompt,
-            sample_len: config.sample_len,
-            queue,
-            processing,
+            models,
+            queue_settings,
             results,
             queue_disabled: config.queue_disabled,
+            redactor,
+            health,
+            in_flight,
+            drainer,
         })
     }
 
+    /// Load the configured models unless `lazy_load` is set, blocking until
+    /// they are resident
+    pub fn load_models(&self) -> anyhow::Result<()> {
+        self.models.load_all()
+    }
+
+    /// Build the admin service operating on this engine instance
+    pub fn admin(&self, log_filter: LogFilterHandle) -> Admin {
+        Admin {
+            log_filter,
+            drainer: self.drainer.clone(),
+            models: Arc::clone(&self.models),
+            results: Arc::clone(&self.results),
+            in_flight: self.in_flight.clone(),
+        }
+    }
+
+    /// Handle used to drain this engine on shutdown
+    pub fn drainer(&self) -> Drainer {
+        self.drainer.clone()
+    }
+
     // Delegate to shared inference logic
-    async fn process_inference_request(&self, req: InferenceRunRequest) -> Result<String, Status> {
-        process_inference_request_shared(&self.model, &self.system_prompt, self.sample_len, req).await
+    async fn process_inference_request(&self, slot: &Arc<ModelSlot>, req: InferenceRunRequest) -> Result<Completion, Status> {
+        process_inference_request_shared(&self.models, slot, &self.redactor, req).await
     }
 }
 
 /// Shared inference processing logic used by both immediate and queued requests
+// The blocking closure returns Status, which clippy considers large
+#[allow(clippy::result_large_err)]
 async fn process_inference_request_shared(
-    model: &Arc<Mutex<Box<dyn cylon_inference_engine::TextGenerator>>>,
-    system_prompt: &str,
-    sample_len: usize,
+    models: &Arc<ModelRegistry>,
+    slot: &Arc<ModelSlot>,
+    redactor: &Redactor,
     req: InferenceRunRequest,
-) -> Result<String, Status> {
-    let mut prompt_vec: Vec<String> = vec![system_prompt.to_string()];
-    
-    for msg in req.messages {
-        let p = Prompt {
-            role: msg.role,
-            content: msg.content,
-        };
-        let json = serde_json::to_string(&p)
-            .map_err(|e| Status::internal(format!("Failed to serialize message: {}", e)))?;
-        prompt_vec.push(json);
-    }
-    let prompt = Arc::new(prompt_vec);
+) -> Result<Completion, Status> {
+    // Defaults may be changed by the admin service, so read them per request
+    let (system_prompt, sample_len) = {
+        let runtime = slot.runtime.read().unwrap();
+        (runtime.system_prompt.clone(), runtime.sample_len)
+    };
+    let sample_len = req.max_tokens.map_or(sample_len, |max_tokens| max_tokens as usize);
+    let overrides = SamplingOverrides::from(&req);
+
+    let mut messages: Vec<Prompt> = Vec::with_capacity(req.messages.len() + 1);
+    messages.push(Prompt {
+        role: String::from("system"),
+        content: system_prompt,
+    });
+    messages.extend(req.messages.into_iter().map(|msg| Prompt {
+        role: msg.role,
+        content: msg.content,
+    }));
 
     let response = tokio::task::spawn_blocking({
-        let model = Arc::clone(model);
-        let prompt = Arc::clone(&prompt);
+        let models = Arc::clone(models);
+        let slot = Arc::clone(slot);
+        let span = tracing::Span::current();
         move || {
-            let rt = tokio::runtime::Handle::current();
-            rt.block_on(async {
-                let model_guard = model.lock().await;
-                model_guard.inference(&prompt, sample_len)
-            })
+            let _guard = span.enter();
+            // Loads the model on first use; requests arriving meanwhile are queued
+            let model_guard = models.acquire(&slot)?;
+            let messages = if model_guard.supports_system_role() {
+                messages
+            } else {
+                merge_system_messages(messages)
+            };
+            let prompt = messages
+                .iter()
+                .map(serde_json::to_string)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| Status::internal(format!("Failed to serialize message: {}", e)))?;
+
+            let mut sampling = model_guard.inference_config();
+            overrides.apply(&mut sampling);
+
+            model_guard
+                .inference(&prompt, sample_len, &sampling)
+                .map_err(inference_error)
         }
     })
     .await
-    .map_err(|e| Status::internal(format!("Task failed: {}", e)))?
-    .map_err(|e| Status::internal(format!("Inference failed: {}", e)))?;
+    .map_err(|e| Status::internal(format!("Task failed: {}", e)))??;
+
+    debug!("Response: {}", redactor.text(&response.text));
 
     Ok(response)
-}
\ No newline at end of file
+}
+
+/// Status for a failed inference; a template raising an exception on the messages is the caller's to fix
+fn inference_error(e: anyhow::Error) -> Status {
+    match e.downcast_ref::<TemplateError>() {
+        Some(e) => Status::invalid_argument(e.to_string()),
+        None => Status::internal(format!("Inference failed: {}", e)),
+    }
+}
+
+/// Fold system messages into the first user turn, for chat templates that reject the system role
+fn merge_system_messages(messages: Vec<Prompt>) -> Vec<Prompt> {
+    let (system, mut turns): (Vec<_>, Vec<_>) = messages.into_iter().partition(|msg| msg.role == "system");
+    let instructions = system
+        .into_iter()
+        .map(|msg| msg.content)
+        .filter(|content| !content.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    if instructions.is_empty() {
+        return turns;
+    }
+
+    match turns.iter_mut().find(|msg| msg.role == "user") {
+        Some(first_user) => first_user.content = format!("{}\n\n{}", instructions, first_user.content),
+        None => turns.insert(0, Prompt {
+            role: String::from("user"),
+            content: instructions,
+        }),
+    }
+    turns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cylon_models::chat_template::TokenizerConfig;
+
+    #[test]
+    fn template_exceptions_are_invalid_arguments() {
+        let tokenizer_config = TokenizerConfig::default()
+            .with_default_template("{{ raise_exception('Conversation roles must alternate') }}")
+            .unwrap();
+        let error = tokenizer_config.render(&[]).unwrap_err().context("Failed to build the prompt");
+
+        let status = inference_error(error);
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(status.message(), "Failed to render chat template: Conversation roles must alternate");
+    }
+
+    #[test]
+    fn other_inference_failures_are_internal() {
+        let tokenizer_config = TokenizerConfig::default().with_default_template("{{ messages | no_such_filter }}").unwrap();
+        let status = inference_error(tokenizer_config.render(&[]).unwrap_err());
+        assert_eq!(status.code(), tonic::Code::Internal);
+
+        let status = inference_error(anyhow::anyhow!("out of memory"));
+        assert_eq!(status.code(), tonic::Code::Internal);
+        assert_eq!(status.message(), "Inference failed: out of memory");
+    }
+}
@@ -0,0 +1,95 @@
+use anyhow::{bail, Context, Result};
+use cylon::tls::TlsListener;
+use cylon_config::ListenerConfig;
+use std::fmt;
+use std::os::unix::fs::FileTypeExt;
+use std::path::PathBuf;
+use tokio::net::{TcpListener, UnixListener};
+use tokio::sync::watch;
+use tokio_stream::wrappers::{TcpListenerStream, UnixListenerStream};
+use tonic::transport::server::Router;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+/// A listener socket bound at startup, before the model is loaded, so bind errors surface early
+pub enum BoundListener {
+    Tcp(TcpListener),
+    Tls(TcpListener, TlsListener),
+    Unix(UnixListener, PathBuf),
+}
+
+impl BoundListener {
+    pub async fn bind(config: &ListenerConfig, tls: Option<&TlsListener>) -> Result<Self> {
+        match (&config.address, &config.unix_socket) {
+            (Some(address), None) => {
+                let listener = TcpListener::bind(address)
+                    .await
+                    .with_context(|| format!("Failed to bind {}", address))?;
+
+                match (config.tls, tls) {
+                    (false, _) => Ok(BoundListener::Tcp(listener)),
+                    (true, Some(tls)) => Ok(BoundListener::Tls(listener, tls.clone())),
+                    (true, None) => bail!("Listener {} requires TLS but tls_cert and tls_key are not set", address),
+                }
+            }
+            (None, Some(path)) => {
+                if config.tls {
+                    bail!("TLS is not supported on Unix socket {}", path);
+                }
+
+                let path = PathBuf::from(path);
+                // A socket left behind by a previous run would otherwise make the bind fail
+                if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+                    if !metadata.file_type().is_socket() {
+                        bail!("{} exists and is not a socket", path.display());
+                    }
+                    std::fs::remove_file(&path)
+                        .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+                }
+
+                let listener = UnixListener::bind(&path)
+                    .with_context(|| format!("Failed to bind Unix socket {}", path.display()))?;
+                Ok(BoundListener::Unix(listener, path))
+            }
+            _ => bail!("A listener needs exactly one of address or unix_socket"),
+        }
+    }
+
+    /// Serve `router` until `shutdown` flips to true
+    pub async fn serve(self, router: Router, mut shutdown: watch::Receiver<bool>) -> Result<()> {
+        info!("Server listening: {}", self);
+        let signal = async move {
+            let _ = shutdown.wait_for(|stop| *stop).await;
+        };
+
+        match self {
+            BoundListener::Tcp(listener) => {
+                router.serve_with_incoming_shutdown(TcpListenerStream::new(listener), signal).await?;
+            }
+            BoundListener::Tls(listener, tls) => {
+                router.serve_with_incoming_shutdown(tls.incoming(listener), signal).await?;
+            }
+            BoundListener::Unix(listener, path) => {
+                router.serve_with_incoming_shutdown(UnixListenerStream::new(listener), signal).await?;
+                if let Err(e) = std::fs::remove_file(&path) {
+                    warn!("Failed to remove Unix socket {}: {}", path.display(), e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for BoundListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let addr = |listener: &TcpListener| listener.local_addr().map(|a| a.to_string()).unwrap_or_default();
+
+        match self {
+            BoundListener::Tcp(listener) => write!(f, "{}", addr(listener)),
+            BoundListener::Tls(listener, _) => write!(f, "{} (TLS)", addr(listener)),
+            BoundListener::Unix(_, path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
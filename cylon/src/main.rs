@@ -1,31 +1,117 @@
-use cylon::{Cylon, cylon_proto::cylon_api_server::CylonApiServer};
-use cylon_config::CylonConfig;
+use cylon::{Cylon, health::Health, http, observability::init_metrics, shutdown::shutdown_signal};
+use cylon::auth::ApiKeyAuth;
+use cylon::tls::{TlsListener, TlsSettings};
+use cylon::cylon_proto::{self, cylon_admin_server::CylonAdminServer, cylon_api_server::CylonApiServer};
+use cylon_config::{Command, CylonConfig, ListenerService};
+use listeners::BoundListener;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tonic::service::interceptor::InterceptedService;
 use tonic::transport::Server;
 use utils::init_logging;
 
 #[allow(unused_imports)]
 use tracing::{info, debug, error, warn};
 
+mod listeners;
 mod utils;
 
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config = CylonConfig::new()?;
 
-    init_logging(config.debug);
+    if let Some(Command::Inspect { model_path, model_family }) = &config.command {
+        let inspection = cylon_models::inspect::inspect(model_path, model_family.as_deref(), config.chat_template.as_deref())?;
+        print!("{inspection}");
+        std::process::exit(if inspection.is_ok() { 0 } else { 1 });
+    }
+
+    let logging = init_logging(&config)?;
 
     info!("Starting Cylon Engine");
 
-    info!("Loading model and creating engine");
-    let cylon = Cylon::new(&config)?;
+    // Load certificates before the model so a bad TLS setup fails fast
+    let tls = TlsSettings::from_config(&config)?.map(TlsListener::new).transpose()?;
+    let auth = ApiKeyAuth::from_config(&config)?;
+
+    let mut listeners = Vec::new();
+    for listener in config.listeners() {
+        let bound = BoundListener::bind(&listener, tls.as_ref()).await?;
+        listeners.push((bound, listener.services));
+    }
+
+    let metrics_handle = init_metrics()?;
+    let (health, health_service) = Health::new(config.queue_buffer_size).await;
+
+    // Start the HTTP probes before loading so /readyz can report the loading state
+    if !config.http_disabled {
+        let http_addr = format!("{}:{}", config.listen_address, config.http_listen_port).parse()?;
+        let http_state = http::HttpState::new(metrics_handle, Arc::clone(&health));
+        tokio::spawn(async move {
+            if let Err(e) = http::serve(http_addr, http_state).await {
+                error!("HTTP server failed: {}", e);
+            }
+        });
+    }
+
+    let cylon = Cylon::new(&config, Arc::clone(&health))?;
+
+    let admin = Arc::new(cylon.admin(logging.filter_handle.clone()));
+    let drainer = cylon.drainer();
+    let cylon = Arc::new(cylon);
+
+    if let Some(tls) = &tls {
+        tls.watch();
+    }
+
+    let mut servers = JoinSet::new();
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    for (listener, services) in listeners {
+        let api = services.contains(&ListenerService::Api)
+            .then(|| InterceptedService::new(CylonApiServer::from_arc(Arc::clone(&cylon)), auth.clone()));
+        let admin = services.contains(&ListenerService::Admin)
+            .then(|| InterceptedService::new(CylonAdminServer::from_arc(Arc::clone(&admin)), auth.admin()));
+
+        // Reflection lets clients such as grpcurl discover the services without the .proto files
+        let reflection_v1 = tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(cylon_proto::FILE_DESCRIPTOR_SET)
+            .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+            .build_v1()?;
+        let reflection_v1alpha = tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(cylon_proto::FILE_DESCRIPTOR_SET)
+            .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+            .build_v1alpha()?;
+
+        let router = Server::builder()
+            .add_optional_service(api)
+            .add_optional_service(admin)
+            .add_service(health_service.clone())
+            .add_service(reflection_v1)
+            .add_service(reflection_v1alpha);
+
+        servers.spawn(listener.serve(router, shutdown_rx.clone()));
+    }
+
+    // Health checks report NOT_SERVING on every listener until the models are resident
+    info!("Loading models");
+    tokio::task::block_in_place(|| cylon.load_models())?;
+    health.set_loaded().await;
+
+    // Keep answering status and result lookups on every listener while outstanding jobs drain
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        drainer.drain().await;
+        info!("Shutting down gRPC server");
+        let _ = shutdown_tx.send(true);
+    });
 
-    let addr = format!("{}:{}", config.listen_address, config.listen_port).parse()?;
-    info!("Server listening: {}", addr);
+    while let Some(result) = servers.join_next().await {
+        result??;
+    }
 
-    Server::builder()
-        .add_service(CylonApiServer::new(cylon))
-        .serve(addr)
-        .await?;
+    logging.shutdown()?;
 
     Ok(())
 }
\ No newline at end of file
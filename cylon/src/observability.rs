@@ -0,0 +1,141 @@
+use anyhow::Result;
+use metrics::{describe_counter, describe_gauge, describe_histogram, Unit};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use opentelemetry::propagation::Extractor;
+use tokio::time;
+use tonic::metadata::{KeyRef, MetadataMap};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// Handle used to swap the active log filter at runtime
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+const TOKENS_PER_SECOND_BUCKETS: &[f64] = &[
+    1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 50.0, 75.0, 100.0, 150.0, 200.0, 500.0,
+];
+
+/// Build a log filter from `RUST_LOG`-style directives (e.g. "info,cylon=debug")
+pub fn build_log_filter(directives: &str) -> Result<EnvFilter> {
+    let filter = EnvFilter::try_new(directives)?
+        .add_directive("tokenizers::tokenizer::serialization=error".parse()?);
+
+    Ok(filter)
+}
+
+/// Install the global Prometheus recorder and describe the metrics exported by Cylon
+///
+/// The returned handle renders the current state of all metrics in the Prometheus
+/// text exposition format and is served on the HTTP listener at `/metrics`.
+pub fn init_metrics() -> Result<PrometheusHandle> {
+    let handle = prometheus_builder()?.install_recorder()?;
+
+    describe_counter!("cylon_requests_total", "Inference requests by final status and model");
+    describe_gauge!("cylon_queue_depth", "Number of requests waiting in each model's prompt queue");
+    describe_gauge!("cylon_model_loaded", "Whether a model's weights are loaded (1) or not (0)");
+    describe_gauge!("cylon_result_cache_entries", "Number of job results held in the result cache");
+    describe_histogram!("cylon_queue_wait_duration_seconds", Unit::Seconds, "Time a queued request waited before processing");
+    describe_histogram!("cylon_prefill_duration_seconds", Unit::Seconds, "Time spent processing the prompt before the first token");
+    describe_histogram!("cylon_decode_duration_seconds", Unit::Seconds, "Time spent generating tokens after the first token");
+    describe_histogram!("cylon_decode_tokens_per_second", "Decode throughput per request in tokens per second");
+    describe_counter!("cylon_prompt_tokens_total", Unit::Count, "Prompt tokens processed");
+    describe_counter!("cylon_tokens_generated_total", Unit::Count, "Tokens generated");
+
+    // Histogram samples are buffered until rendered; periodic upkeep keeps memory bounded between scrapes
+    let upkeep_handle = handle.clone();
+    tokio::spawn(async move {
+        let mut interval = time::interval(time::Duration::from_secs(5));
+
+        loop {
+            interval.tick().await;
+            upkeep_handle.run_upkeep();
+        }
+    });
+
+    Ok(handle)
+}
+
+/// Prometheus exporter with histogram buckets sized for request durations and decode throughput
+fn prometheus_builder() -> Result<PrometheusBuilder> {
+    let builder = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), DURATION_BUCKETS)?
+        .set_buckets_for_metric(
+            Matcher::Full("cylon_decode_tokens_per_second".to_string()),
+            TOKENS_PER_SECOND_BUCKETS,
+        )?;
+
+    Ok(builder)
+}
+
+/// Adapts gRPC request metadata for OpenTelemetry context propagation
+struct MetadataExtractor<'a>(&'a MetadataMap);
+
+impl Extractor for MetadataExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0
+            .keys()
+            .map(|key| match key {
+                KeyRef::Ascii(key) => key.as_str(),
+                KeyRef::Binary(key) => key.as_str(),
+            })
+            .collect()
+    }
+}
+
+/// Extract the W3C trace context propagated by the caller in gRPC metadata
+pub fn extract_trace_context(metadata: &MetadataMap) -> opentelemetry::Context {
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&MetadataExtractor(metadata))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::TraceContextExt;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+
+    #[test]
+    fn histograms_use_configured_buckets() {
+        let recorder = prometheus_builder().unwrap().build_recorder();
+        let handle = recorder.handle();
+        metrics::with_local_recorder(&recorder, || {
+            metrics::histogram!("cylon_queue_wait_duration_seconds").record(0.2);
+            metrics::histogram!("cylon_decode_tokens_per_second").record(42.0);
+        });
+
+        let rendered = handle.render();
+        assert!(rendered.contains("cylon_queue_wait_duration_seconds_bucket{le=\"0.1\"} 0"), "{rendered}");
+        assert!(rendered.contains("cylon_queue_wait_duration_seconds_bucket{le=\"0.25\"} 1"), "{rendered}");
+        assert!(rendered.contains("cylon_decode_tokens_per_second_bucket{le=\"30\"} 0"), "{rendered}");
+        assert!(rendered.contains("cylon_decode_tokens_per_second_bucket{le=\"50\"} 1"), "{rendered}");
+    }
+
+    #[test]
+    fn extracts_the_callers_trace_context() {
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        let mut metadata = MetadataMap::new();
+        metadata.insert("traceparent", "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".parse().unwrap());
+
+        let context = extract_trace_context(&metadata);
+        let span = context.span();
+        let span_context = span.span_context();
+        assert!(span_context.is_remote());
+        assert_eq!(span_context.trace_id().to_string(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(span_context.span_id().to_string(), "00f067aa0ba902b7");
+        assert!(span_context.is_sampled());
+    }
+
+    #[test]
+    fn starts_a_new_trace_without_a_traceparent() {
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        let context = extract_trace_context(&MetadataMap::new());
+        assert!(!context.span().span_context().is_valid());
+    }
+}
@@ -1,29 +1,62 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::mpsc::{self, Receiver, Sender};
+use tracing::{info_span, Span};
+use crate::access_log::AccessRecord;
+use crate::auth::Owner;
 use crate::cylon_proto::InferenceRunRequest;
+use crate::health::Health;
 
+/// Who submitted a job and when, as reported by the admin service
 #[derive(Debug, Clone)]
-pub struct QueuedRequest {
+pub struct JobSummary {
     pub job_id: String,
+    pub owner: Owner,
+    pub model: String,
+    pub received_at: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct QueuedRequest {
+    pub job: JobSummary,
     pub request: InferenceRunRequest,
+    pub enqueued_at: Instant,
+    pub access: AccessRecord,
+    /// Span of the submitting request, so the job stays in the caller's trace
+    pub span: Span,
+    /// Open for as long as the job waits in the queue
+    pub queue_wait_span: Span,
 }
 
 #[derive(Debug)]
 pub struct PromptQueue {
+    model: String,
     sender: Sender<QueuedRequest>,
     receiver: Receiver<QueuedRequest>,
-    queue_len: usize,
+    /// Mirrors the channel contents in order, since a channel cannot be inspected
+    pending: VecDeque<JobSummary>,
+    health: Arc<Health>,
 }
 
 impl PromptQueue {
-    pub fn new(buffer_size: usize) -> Self {
+    pub fn new(model: String, buffer_size: usize, health: Arc<Health>) -> Self {
         let (sender, receiver) = mpsc::channel(buffer_size);  // Bounded to prevent overload
-        PromptQueue { sender, receiver, queue_len: 0 }
+        PromptQueue { model, sender, receiver, pending: VecDeque::new(), health }
     }
 
-    pub async fn enqueue(&mut self, job_id: String, req: InferenceRunRequest) -> Result<(), String> {
-        let queued_req = QueuedRequest { job_id, request: req };
+    pub async fn enqueue(&mut self, job: JobSummary, req: InferenceRunRequest, access: AccessRecord) -> Result<(), String> {
+        let queued_req = QueuedRequest {
+            job: job.clone(),
+            request: req,
+            enqueued_at: Instant::now(),
+            access,
+            span: Span::current(),
+            queue_wait_span: info_span!("queue_wait"),
+        };
         self.sender.send(queued_req).await.map_err(|e| format!("Queue full: {}", e))?;
-        self.queue_len += 1;
+        self.pending.push_back(job);
+        self.depth_changed().await;
         Ok(())
     }
 
@@ -31,7 +64,8 @@ impl PromptQueue {
         // Use try_recv to check if there's an item immediately available
         match self.receiver.try_recv() {
             Ok(item) => {
-                self.queue_len = self.queue_len.saturating_sub(1);
+                self.pending.pop_front();
+                self.depth_changed().await;
                 Some(item)
             }
             Err(tokio::sync::mpsc::error::TryRecvError::Empty) => None,
@@ -39,5 +73,18 @@ impl PromptQueue {
         }
     }
 
+    /// Get the number of requests currently waiting in the queue
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Jobs waiting in the queue, oldest first
+    pub fn jobs(&self) -> Vec<JobSummary> {
+        self.pending.iter().cloned().collect()
+    }
 
+    async fn depth_changed(&self) {
+        metrics::gauge!("cylon_queue_depth", "model" => self.model.clone()).set(self.pending.len() as f64);
+        self.health.set_queue_depth(&self.model, self.pending.len()).await;
+    }
 }
\ No newline at end of file
@@ -1,54 +1,94 @@
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use tonic::Status;
+use tracing::Instrument;
 
 use crate::cylon_proto::{InferenceRunRequest, InferenceRunReply, Message};
-use crate::prompt_queue::PromptQueue;
+use crate::redaction::Redactor;
+use crate::registry::{ModelRegistry, ModelSlot};
+use crate::shutdown::InFlight;
 use crate::result_cache::ResultCache;
-use cylon_inference_engine::TextGenerator;
+use cylon_inference_engine::Completion;
 
 #[allow(unused_imports)]
 use tracing::{info, debug, error, warn};
 
-// Helper struct for queue processing in background tasks
+// Helper struct for processing one model's queue in background tasks
 pub struct QueueProcessor {
-    pub queue: Arc<Mutex<PromptQueue>>,
-    pub processing: Arc<Mutex<bool>>,
+    pub models: Arc<ModelRegistry>,
+    pub slot: Arc<ModelSlot>,
     pub results: Arc<ResultCache<String, InferenceRunReply>>,
-    pub model: Arc<Mutex<Box<dyn TextGenerator>>>,
-    pub system_prompt: String,
-    pub sample_len: usize,
+    pub redactor: Arc<Redactor>,
+    pub in_flight: InFlight,
+}
+
+/// Starts a [`QueueProcessor`] for its model when dropped
+///
+/// Held by a request that set the processing flag to run immediately, so the
+/// queue is picked up whether that request completes, fails or is cancelled.
+pub struct QueueHandoff(Option<QueueProcessor>);
+
+impl QueueHandoff {
+    pub fn new(processor: QueueProcessor) -> Self {
+        QueueHandoff(Some(processor))
+    }
+}
+
+impl Drop for QueueHandoff {
+    fn drop(&mut self) {
+        let Some(processor) = self.0.take() else {
+            return;
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(async move { processor.process_queue().await });
+            }
+            Err(_) => warn!("No runtime to process the queue of model {}", processor.slot.name),
+        }
+    }
 }
 
 impl QueueProcessor {
     pub async fn process_queue(&self) {
         loop {
-            let mut queue = self.queue.lock().await;
+            let mut queue = self.slot.queue.lock().await;
             if let Some(queued_request) = queue.dequeue().await {
-                let job_id = queued_request.job_id.clone();
+                // Count the job before releasing the queue lock so a drain never sees it in neither place
+                let _in_flight = self.in_flight.enter(queued_request.job.clone());
+                let job_id = queued_request.job.job_id.clone();
                 let request = queued_request.request;
+                let owner = queued_request.job.owner;
                 drop(queue); // Release queue lock
+
+                drop(queued_request.queue_wait_span);
+                metrics::histogram!("cylon_queue_wait_duration_seconds")
+                    .record(queued_request.enqueued_at.elapsed().as_secs_f64());
                 
                 debug!("Processing queued request with job_id: {}", job_id);
-                
+
+                let mut access = queued_request.access;
+                access.start();
+
                 // Process the queued request
-                if let Ok(response) = self.process_inference_request(request).await {
+                if let Ok(completion) = self.process_inference_request(request).instrument(queued_request.span).await {
+                    access.finish("COMPLETED", Some(&completion));
+
                     // Store the result using DashMap
-                    self.results.insert(job_id.clone(), InferenceRunReply {
+                    self.results.insert(job_id.clone(), owner, InferenceRunReply {
                         response: Some(Message {
                             role: "assistant".to_string(),
-                            content: response,
+                            content: completion.text,
                         }),
                         status: "COMPLETED".to_string(),
                         uuid: job_id.clone(),
                     });
-                    
+
                     debug!("Completed queued request: {}", job_id);
                 } else {
                     error!("Failed to process queued request: {}", job_id);
+                    access.finish("ERROR", None);
                     
                     // Store error result using DashMap
-                    self.results.insert(job_id.clone(), InferenceRunReply {
+                    self.results.insert(job_id.clone(), owner, InferenceRunReply {
                         response: None,
                         status: "ERROR".to_string(),
                         uuid: job_id,
@@ -57,7 +97,7 @@ impl QueueProcessor {
                 // Continue processing next item in queue
             } else {
                 // No more items in queue, reset processing flag and exit
-                let mut processing = self.processing.lock().await;
+                let mut processing = self.slot.processing.lock().await;
                 *processing = false;
                 debug!("Queue empty, reset processing flag to false");
                 break;
@@ -65,7 +105,7 @@ impl QueueProcessor {
         }
     }
 
-    async fn process_inference_request(&self, req: InferenceRunRequest) -> Result<String, Status> {
-        crate::process_inference_request_shared(&self.model, &self.system_prompt, self.sample_len, req).await
+    async fn process_inference_request(&self, req: InferenceRunRequest) -> Result<Completion, Status> {
+        crate::process_inference_request_shared(&self.models, &self.slot, &self.redactor, req).await
     }
 }
\ No newline at end of file
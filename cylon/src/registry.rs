@@ -0,0 +1,564 @@
+use anyhow::{anyhow, bail, Result};
+use cylon_config::CylonConfig;
+use cylon_inference_engine::{InferenceConfig, TextGenerator};
+use cylon_models::{create_model, estimate_model_size};
+use cylon_models::utils::sampling_defaults;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{MappedMutexGuard, Mutex, MutexGuard};
+use tonic::Status;
+
+use crate::cylon_proto::{InferenceRunRequest, ModelInfo, SetDefaultsRequest};
+use crate::health::Health;
+use crate::prompt_queue::PromptQueue;
+use crate::sampling_settings;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+const MB: u64 = 1024 * 1024;
+
+/// Model metadata and generation defaults that the admin service can change at runtime
+#[derive(Debug, Clone)]
+pub(crate) struct Runtime {
+    pub model_path: String,
+    pub model_family: String,
+    /// Properties of the weights, known once the model has been loaded
+    pub info: Option<cylon_inference_engine::ModelInfo>,
+    pub system_prompt: String,
+    pub sample_len: usize,
+    /// The loaded checkpoint's defaults with `overrides` applied
+    pub sampling: InferenceConfig,
+    /// Changed via the admin service; kept across reloads while checkpoint defaults are not
+    pub overrides: SamplingOverrides,
+}
+
+impl Runtime {
+    fn new(config: &CylonConfig) -> Result<Self> {
+        Ok(Runtime {
+            model_path: config.model_path.clone(),
+            model_family: config.model_family.clone(),
+            info: None,
+            system_prompt: config.system_prompt.clone(),
+            sample_len: config.sample_len,
+            sampling: sampling_defaults(config)?,
+            overrides: SamplingOverrides::default(),
+        })
+    }
+
+    /// Record a newly loaded model, keeping the generation defaults
+    pub fn set_model(&mut self, config: &CylonConfig, model: &dyn TextGenerator) {
+        self.model_path = config.model_path.clone();
+        self.model_family = config.model_family.clone();
+        self.info = Some(model.info());
+    }
+}
+
+/// Sampling settings set through the admin service, layered over each checkpoint's defaults
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SamplingOverrides {
+    pub temperature: Option<f64>,
+    /// 0 disables top-p sampling
+    pub top_p: Option<f64>,
+    /// 0 disables top-k sampling
+    pub top_k: Option<usize>,
+    pub seed: Option<u64>,
+    pub repeat_penalty: Option<f32>,
+    pub repeat_last_n: Option<usize>,
+}
+
+impl From<&SetDefaultsRequest> for SamplingOverrides {
+    fn from(req: &SetDefaultsRequest) -> Self {
+        SamplingOverrides {
+            temperature: req.temperature,
+            top_p: req.top_p,
+            top_k: req.top_k.map(|top_k| top_k as usize),
+            seed: req.seed,
+            repeat_penalty: req.repeat_penalty,
+            repeat_last_n: req.repeat_last_n.map(|repeat_last_n| repeat_last_n as usize),
+        }
+    }
+}
+
+impl From<&InferenceRunRequest> for SamplingOverrides {
+    fn from(req: &InferenceRunRequest) -> Self {
+        SamplingOverrides {
+            temperature: req.temperature,
+            top_p: req.top_p,
+            top_k: req.top_k.map(|top_k| top_k as usize),
+            seed: req.seed,
+            repeat_penalty: req.repeat_penalty,
+            repeat_last_n: req.repeat_last_n.map(|repeat_last_n| repeat_last_n as usize),
+        }
+    }
+}
+
+impl SamplingOverrides {
+    /// Reject settings outside the range the sampler accepts
+    #[allow(clippy::result_large_err)]
+    pub fn validate(&self) -> Result<(), Status> {
+        if self.temperature.is_some_and(|t| t < 0.0) {
+            return Err(Status::invalid_argument("temperature must not be negative"));
+        }
+        if self.top_p.is_some_and(|p| !(0.0..=1.0).contains(&p)) {
+            return Err(Status::invalid_argument("top_p must be between 0 and 1"));
+        }
+        if self.repeat_penalty.is_some_and(|p| p <= 0.0) {
+            return Err(Status::invalid_argument("repeat_penalty must be greater than 0"));
+        }
+        Ok(())
+    }
+
+    /// Take the settings `other` sets, keeping earlier ones it leaves unset
+    pub fn merge(&mut self, other: SamplingOverrides) {
+        self.temperature = other.temperature.or(self.temperature);
+        self.top_p = other.top_p.or(self.top_p);
+        self.top_k = other.top_k.or(self.top_k);
+        self.seed = other.seed.or(self.seed);
+        self.repeat_penalty = other.repeat_penalty.or(self.repeat_penalty);
+        self.repeat_last_n = other.repeat_last_n.or(self.repeat_last_n);
+    }
+
+    pub fn apply(&self, sampling: &mut InferenceConfig) {
+        if let Some(temperature) = self.temperature {
+            sampling.temperature = temperature;
+        }
+        if let Some(top_p) = self.top_p {
+            sampling.top_p = (top_p > 0.0).then_some(top_p);
+        }
+        if let Some(top_k) = self.top_k {
+            sampling.top_k = (top_k > 0).then_some(top_k);
+        }
+        if let Some(seed) = self.seed {
+            sampling.seed = Some(seed);
+        }
+        if let Some(repeat_penalty) = self.repeat_penalty {
+            sampling.repeat_penalty = repeat_penalty;
+        }
+        if let Some(repeat_last_n) = self.repeat_last_n {
+            sampling.repeat_last_n = repeat_last_n;
+        }
+    }
+}
+
+/// A named model with its own generation defaults and prompt queue
+///
+/// The weights are only resident while `model` holds a value; they are loaded
+/// on demand through `ModelRegistry::acquire`.
+#[derive(Debug)]
+pub(crate) struct ModelSlot {
+    pub name: String,
+    pub model: Arc<Mutex<Option<Box<dyn TextGenerator>>>>,
+    pub runtime: RwLock<Runtime>,
+    /// Config the model is loaded with
+    pub config: RwLock<CylonConfig>,
+    /// Held for the duration of an admin reload
+    pub reload_lock: Mutex<()>,
+    pub queue: Mutex<PromptQueue>,
+    pub processing: Mutex<bool>,
+    /// Set while the model is being replaced, so new requests for it are refused
+    reloading: AtomicBool,
+    /// Mirrors whether `model` holds a value, readable without waiting on the model lock
+    loaded: AtomicBool,
+    /// Approximate size of the weights in bytes
+    size: AtomicU64,
+    last_used: std::sync::Mutex<Instant>,
+}
+
+impl ModelSlot {
+    fn new(name: String, config: CylonConfig, health: Arc<Health>) -> Result<Self> {
+        let size = estimate_model_size(&config)?;
+        let queue = Mutex::new(PromptQueue::new(name.clone(), config.queue_buffer_size, health));
+
+        Ok(ModelSlot {
+            name,
+            model: Arc::new(Mutex::new(None)),
+            runtime: RwLock::new(Runtime::new(&config)?),
+            config: RwLock::new(config),
+            reload_lock: Mutex::new(()),
+            queue,
+            processing: Mutex::new(false),
+            reloading: AtomicBool::new(false),
+            loaded: AtomicBool::new(false),
+            size: AtomicU64::new(size),
+            last_used: std::sync::Mutex::new(Instant::now()),
+        })
+    }
+
+    pub fn is_reloading(&self) -> bool {
+        self.reloading.load(Ordering::SeqCst)
+    }
+
+    /// Refuse new requests for the model until the returned guard is dropped
+    pub fn start_reloading(&self) -> Reloading<'_> {
+        self.reloading.store(true, Ordering::SeqCst);
+        Reloading(self)
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.loaded.load(Ordering::SeqCst)
+    }
+
+    /// Install a model loaded from `config`, replacing any current one
+    pub fn install(&self, model: &mut Option<Box<dyn TextGenerator>>, mut new_model: Box<dyn TextGenerator>, config: CylonConfig, size: u64) {
+        let mut runtime = self.runtime.write().unwrap();
+        // Start from the checkpoint's own defaults so a reload picks up its generation_config.json
+        let mut sampling = new_model.inference_config();
+        runtime.overrides.apply(&mut sampling);
+        new_model.set_inference_config(sampling.clone());
+        runtime.sampling = sampling;
+        runtime.set_model(&config, new_model.as_ref());
+        drop(runtime);
+
+        *model = Some(new_model);
+        *self.config.write().unwrap() = config;
+        self.size.store(size, Ordering::SeqCst);
+        self.loaded.store(true, Ordering::SeqCst);
+        metrics::gauge!("cylon_model_loaded", "model" => self.name.clone()).set(1.0);
+    }
+
+    fn unload(&self, model: &mut Option<Box<dyn TextGenerator>>) {
+        *model = None;
+        self.loaded.store(false, Ordering::SeqCst);
+        metrics::gauge!("cylon_model_loaded", "model" => self.name.clone()).set(0.0);
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_used.lock().unwrap().elapsed()
+    }
+
+    pub fn describe(&self) -> ModelInfo {
+        let runtime = self.runtime.read().unwrap();
+        let info = runtime.info.clone().unwrap_or_else(|| cylon_inference_engine::ModelInfo {
+            family: runtime.model_family.clone(),
+            ..Default::default()
+        });
+
+        ModelInfo {
+            name: self.name.clone(),
+            family: info.family,
+            context_length: info.context_length as u64,
+            dtype: info.dtype,
+            device: info.device,
+            vocab_size: info.vocab_size as u64,
+            has_chat_template: info.has_chat_template,
+            path: runtime.model_path.clone(),
+            max_tokens: runtime.sample_len as u64,
+            sampling: Some(sampling_settings(&runtime.sampling)),
+            loaded: self.is_loaded(),
+        }
+    }
+}
+
+/// Clears a model's `reloading` flag when dropped, however the reload ends
+pub(crate) struct Reloading<'a>(&'a ModelSlot);
+
+impl Drop for Reloading<'_> {
+    fn drop(&mut self) {
+        self.0.reloading.store(false, Ordering::SeqCst);
+    }
+}
+
+/// The models this server routes requests to, in configuration order
+#[derive(Debug)]
+pub(crate) struct ModelRegistry {
+    slots: Vec<Arc<ModelSlot>>,
+    default: usize,
+    /// Bytes of weights to keep resident
+    memory_budget: Option<u64>,
+    idle_timeout: Option<Duration>,
+    /// Load models on first use instead of in `load_all`
+    lazy_load: bool,
+    /// Serializes loads so two models never claim the same share of the budget
+    load_lock: Mutex<()>,
+}
+
+impl ModelRegistry {
+    /// Set up the configured models without loading any weights
+    pub fn new(config: &CylonConfig, health: &Arc<Health>) -> Result<Self> {
+        let slots = config
+            .models()?
+            .into_iter()
+            .map(|(name, config)| ModelSlot::new(name, config, Arc::clone(health)).map(Arc::new))
+            .collect::<Result<Vec<_>>>()?;
+
+        let default = config.default_model
+            .as_ref()
+            .and_then(|name| slots.iter().position(|slot| slot.name == *name))
+            .unwrap_or(0);
+
+        let memory_budget = config.model_memory_budget.map(|budget| budget * MB);
+        if let Some(budget) = memory_budget {
+            for slot in &slots {
+                let size = slot.size.load(Ordering::SeqCst);
+                if size > budget {
+                    bail!("Model {} needs about {} MB, more than the memory budget of {} MB", slot.name, size.div_ceil(MB), budget / MB);
+                }
+            }
+
+            let total: u64 = slots.iter().map(|slot| slot.size.load(Ordering::SeqCst)).sum();
+            if !config.lazy_load && total > budget {
+                bail!("Models need about {} MB together, more than the memory budget of {} MB; enable lazy_load", total.div_ceil(MB), budget / MB);
+            }
+        }
+
+        let registry = ModelRegistry {
+            slots,
+            default,
+            memory_budget,
+            idle_timeout: (config.model_idle_timeout > 0).then(|| Duration::from_secs(config.model_idle_timeout)),
+            lazy_load: config.lazy_load,
+            load_lock: Mutex::new(()),
+        };
+
+        Ok(registry)
+    }
+
+    /// Load every model now unless `lazy_load` is set
+    ///
+    /// Blocks until the weights are resident, so it must be called from a
+    /// blocking thread.
+    pub fn load_all(&self) -> Result<()> {
+        if !self.lazy_load {
+            for slot in &self.slots {
+                drop(self.acquire(slot).map_err(|e| anyhow!(e.message().to_string()))?);
+            }
+        }
+        info!("Serving {} models, default {}", self.slots.len(), self.default().name);
+        Ok(())
+    }
+
+    /// Look up a model by name; an empty name selects the default model
+    pub fn get(&self, name: &str) -> Option<&Arc<ModelSlot>> {
+        if name.is_empty() {
+            return Some(self.default());
+        }
+        self.slots.iter().find(|slot| slot.name == name)
+    }
+
+    pub fn default(&self) -> &Arc<ModelSlot> {
+        &self.slots[self.default]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<ModelSlot>> {
+        self.slots.iter()
+    }
+
+    /// Lock a model for inference, loading it first if it is not resident
+    ///
+    /// Blocks while the model loads or other models are unloaded to make room,
+    /// so it must be called from a blocking thread.
+    #[allow(clippy::result_large_err)]
+    pub fn acquire<'a>(&self, slot: &'a ModelSlot) -> Result<MappedMutexGuard<'a, Box<dyn TextGenerator>>, Status> {
+        let mut model = slot.model.blocking_lock();
+        *slot.last_used.lock().unwrap() = Instant::now();
+
+        if model.is_none() {
+            let _loading = self.load_lock.blocking_lock();
+            self.make_room(slot)?;
+
+            let config = slot.config.read().unwrap().clone();
+            info!("Loading model {} from {}", slot.name, config.model_path);
+            let started = Instant::now();
+            let new_model = create_model(&config)
+                .map_err(|e| Status::unavailable(format!("Failed to load model {}: {:#}", slot.name, e)))?;
+            let size = slot.size.load(Ordering::SeqCst);
+            slot.install(&mut model, new_model, config, size);
+            info!("Loaded model {} in {:?}", slot.name, started.elapsed());
+        }
+
+        Ok(MutexGuard::map(model, |model| model.as_mut().expect("model was just loaded")))
+    }
+
+    /// Unload least recently used models until `slot` fits in the memory budget
+    #[allow(clippy::result_large_err)]
+    fn make_room(&self, slot: &ModelSlot) -> Result<(), Status> {
+        let Some(budget) = self.memory_budget else {
+            return Ok(());
+        };
+        let needed = slot.size.load(Ordering::SeqCst);
+
+        loop {
+            let resident: u64 = self.slots
+                .iter()
+                .filter(|other| other.is_loaded())
+                .map(|other| other.size.load(Ordering::SeqCst))
+                .sum();
+            if resident + needed <= budget {
+                return Ok(());
+            }
+
+            let victim = self.slots
+                .iter()
+                .filter(|other| other.is_loaded() && other.name != slot.name)
+                .max_by_key(|other| other.idle_for())
+                .ok_or_else(|| Status::resource_exhausted(format!("Not enough memory budget to load model {}", slot.name)))?;
+
+            info!("Unloading model {} to make room for {}", victim.name, slot.name);
+            // Waits for the victim's running generation to finish
+            let mut model = victim.model.blocking_lock();
+            victim.unload(&mut model);
+        }
+    }
+
+    /// Periodically unload models that have not been used for `model_idle_timeout`
+    pub fn start_idle_unloader(registry: Arc<Self>) {
+        let Some(idle_timeout) = registry.idle_timeout else {
+            return;
+        };
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(idle_timeout.min(Duration::from_secs(30)));
+            loop {
+                interval.tick().await;
+
+                for slot in registry.iter() {
+                    if !slot.is_loaded() || slot.idle_for() < idle_timeout {
+                        continue;
+                    }
+
+                    // Skip models with queued or running work rather than wait for them
+                    let Ok(processing) = slot.processing.try_lock() else {
+                        continue;
+                    };
+                    let Ok(queue) = slot.queue.try_lock() else {
+                        continue;
+                    };
+                    let Ok(mut model) = slot.model.try_lock() else {
+                        continue;
+                    };
+                    if *processing || queue.len() > 0 {
+                        continue;
+                    }
+
+                    info!("Unloading model {} after {:?} idle", slot.name, slot.idle_for());
+                    // Freeing the weights can take a while, so keep it off the async workers;
+                    // the model stays locked and counted against the budget until it is done
+                    let weights = model.take();
+                    if let Err(e) = tokio::task::spawn_blocking(move || drop(weights)).await {
+                        error!("Failed to free weights of model {}: {}", slot.name, e);
+                    }
+                    slot.unload(&mut model);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// A model directory holding only a `generation_config.json`, removed when dropped
+    struct ModelDir(PathBuf);
+
+    impl ModelDir {
+        fn new(test: &str, generation_config: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("cylon-{}-{}", test, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("generation_config.json"), generation_config).unwrap();
+            ModelDir(dir)
+        }
+
+        /// Config serving this directory, with `sampling` YAML for the top-level sampling settings
+        fn config(&self, sampling: &str) -> CylonConfig {
+            let yaml = format!(
+                "debug: false\nlisten_address: 127.0.0.1\nlisten_port: \"8080\"\nqueue_disabled: false\n\
+                 queue_type: local\nqueue_buffer_size: 10\nresult_cache_ttl: 60\nmodel_family: llama\n\
+                 model_path: {}\nseed: 1\nsample_len: 100\nenable_kv_cache: true\nsystem_prompt: \"\"\n\
+                 use_flash_attn: false\nrepeat_last_n: 64\n{sampling}",
+                self.0.display(),
+            );
+            serde_yaml::from_str(&yaml).unwrap()
+        }
+    }
+
+    impl Drop for ModelDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const SAMPLED: &str = r#"{"do_sample": true, "temperature": 0.6, "top_p": 0.9, "top_k": 50, "repetition_penalty": 1.2}"#;
+
+    #[test]
+    fn defaults_come_from_the_generation_config_when_sampling() {
+        let dir = ModelDir::new("sampled", SAMPLED);
+        let sampling = sampling_defaults(&dir.config("")).unwrap();
+        assert_eq!(sampling.temperature, 0.6);
+        assert_eq!(sampling.top_p, Some(0.9));
+        assert_eq!(sampling.top_k, Some(50));
+        assert_eq!(sampling.repeat_penalty, 1.2);
+        assert_eq!(sampling.seed, Some(1));
+        assert_eq!(sampling.repeat_last_n, 64);
+    }
+
+    #[test]
+    fn greedy_generation_configs_only_set_the_repeat_penalty() {
+        let dir = ModelDir::new("greedy", r#"{"temperature": 0.6, "top_p": 0.9, "repetition_penalty": 1.2}"#);
+        let sampling = sampling_defaults(&dir.config("")).unwrap();
+        assert_eq!(sampling.temperature, 0.0);
+        assert_eq!(sampling.top_p, None);
+        assert_eq!(sampling.top_k, None);
+        assert_eq!(sampling.repeat_penalty, 1.2);
+    }
+
+    #[test]
+    fn configured_settings_win_over_the_generation_config() {
+        let dir = ModelDir::new("configured", SAMPLED);
+        let config = dir.config("temperature: 0.2\ntop_p: 0.0\ntop_k: 10\nrepeat_penalty: 1.0\n");
+        let sampling = sampling_defaults(&config).unwrap();
+        assert_eq!(sampling.temperature, 0.2);
+        assert_eq!(sampling.top_p, None);
+        assert_eq!(sampling.top_k, Some(10));
+        assert_eq!(sampling.repeat_penalty, 1.0);
+    }
+
+    #[test]
+    fn request_settings_layer_over_admin_overrides() {
+        let dir = ModelDir::new("layered", SAMPLED);
+        let mut sampling = sampling_defaults(&dir.config("")).unwrap();
+
+        let mut admin = SamplingOverrides::from(&SetDefaultsRequest {
+            temperature: Some(0.3),
+            top_k: Some(0),
+            ..Default::default()
+        });
+        admin.merge(SamplingOverrides::from(&SetDefaultsRequest { seed: Some(5), ..Default::default() }));
+        admin.apply(&mut sampling);
+
+        let request = SamplingOverrides::from(&InferenceRunRequest {
+            top_p: Some(0.0),
+            seed: Some(7),
+            repeat_last_n: Some(16),
+            ..Default::default()
+        });
+        request.apply(&mut sampling);
+
+        assert_eq!(sampling.temperature, 0.3);
+        assert_eq!(sampling.top_k, None);
+        assert_eq!(sampling.top_p, None);
+        assert_eq!(sampling.seed, Some(7));
+        assert_eq!(sampling.repeat_penalty, 1.2);
+        assert_eq!(sampling.repeat_last_n, 16);
+    }
+
+    #[test]
+    fn rejects_settings_the_sampler_cannot_use() {
+        let invalid = [
+            InferenceRunRequest { temperature: Some(-0.1), ..Default::default() },
+            InferenceRunRequest { top_p: Some(1.5), ..Default::default() },
+            InferenceRunRequest { repeat_penalty: Some(0.0), ..Default::default() },
+        ];
+        for request in &invalid {
+            let status = SamplingOverrides::from(request).validate().unwrap_err();
+            assert_eq!(status.code(), tonic::Code::InvalidArgument, "{request:?}");
+        }
+
+        let valid = InferenceRunRequest { temperature: Some(0.0), top_p: Some(1.0), repeat_penalty: Some(1.1), ..Default::default() };
+        assert!(SamplingOverrides::from(&valid).validate().is_ok());
+    }
+}
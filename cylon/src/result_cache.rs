@@ -3,10 +3,11 @@ use chrono::{DateTime, Utc, Duration};
 use std::hash::Hash;
 use std::sync::Arc;
 use tokio::time;
+use crate::auth::Owner;
 
 #[derive(Debug)]
 pub struct ResultCache<K: Eq + Hash + Clone, V> {
-    cache: DashMap<K, (V, DateTime<Utc>)>,
+    cache: DashMap<K, (V, Owner, DateTime<Utc>)>,
     ttl: Duration,
 }
 
@@ -18,29 +19,57 @@ impl<K: Eq + Hash + Clone, V: Clone> ResultCache<K, V> {
         }
     }
 
-    pub fn get(&self, key: &K) -> Option<V> {
+    /// Look up an entry along with the principal that submitted it
+    pub fn get(&self, key: &K) -> Option<(V, Owner)> {
         if let Some(entry) = self.cache.get(key) {
-            let (value, timestamp) = entry.value();
+            let (value, owner, timestamp) = entry.value();
             if Utc::now() - *timestamp < self.ttl {
-                return Some(value.clone());
+                return Some((value.clone(), owner.clone()));
             } else {
                 drop(entry);
                 self.cache.remove(key);
+                self.record_len();
             }
         }
         None
     }
 
-    pub fn insert(&self, key: K, value: V) {
-        self.cache.insert(key, (value, Utc::now()));
+    pub fn insert(&self, key: K, owner: Owner, value: V) {
+        self.cache.insert(key, (value, owner, Utc::now()));
+        self.record_len();
     }
 
     /// Remove all expired entries from the cache
     pub fn cleanup_expired(&self) {
         let now = Utc::now();
-        self.cache.retain(|_, (_, timestamp)| {
+        self.cache.retain(|_, (_, _, timestamp)| {
             now - *timestamp < self.ttl
         });
+        self.record_len();
+    }
+
+    /// Remove one entry, returning whether it was present
+    pub fn remove(&self, key: &K) -> bool {
+        let removed = self.cache.remove(key).is_some();
+        self.record_len();
+        removed
+    }
+
+    /// Remove every entry, returning how many there were
+    pub fn clear(&self) -> usize {
+        let count = self.cache.len();
+        self.cache.clear();
+        self.record_len();
+        count
+    }
+
+    /// Remove entries inserted more than `age` ago, returning how many were removed
+    pub fn expire_older_than(&self, age: Duration) -> usize {
+        let before = self.cache.len();
+        let now = Utc::now();
+        self.cache.retain(|_, (_, _, timestamp)| now - *timestamp < age);
+        self.record_len();
+        before.saturating_sub(self.cache.len())
     }
 
     /// Get the current number of entries in the cache
@@ -48,6 +77,10 @@ impl<K: Eq + Hash + Clone, V: Clone> ResultCache<K, V> {
         self.cache.len()
     }
 
+    fn record_len(&self) {
+        metrics::gauge!("cylon_result_cache_entries").set(self.cache.len() as f64);
+    }
+
     /// Start a background task that periodically cleans up expired entries
     /// 
     /// # Arguments
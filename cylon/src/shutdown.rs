@@ -0,0 +1,187 @@
+use dashmap::DashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::{self, Instant};
+
+use crate::cylon_proto::InferenceRunReply;
+use crate::health::Health;
+use crate::prompt_queue::JobSummary;
+use crate::registry::{ModelRegistry, ModelSlot};
+use crate::result_cache::ResultCache;
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+/// Tracks jobs that have been admitted for immediate processing or taken off the queue
+#[derive(Debug, Clone, Default)]
+pub struct InFlight(Arc<DashMap<String, JobSummary>>);
+
+/// Removes the job it tracks from the in-flight set when the job is done
+pub struct InFlightGuard {
+    jobs: Arc<DashMap<String, JobSummary>>,
+    job_id: String,
+}
+
+impl InFlight {
+    pub fn enter(&self, job: JobSummary) -> InFlightGuard {
+        let job_id = job.job_id.clone();
+        self.0.insert(job_id.clone(), job);
+        InFlightGuard { jobs: Arc::clone(&self.0), job_id }
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Number of jobs running on the named model
+    pub fn count_model(&self, model: &str) -> usize {
+        self.0.iter().filter(|entry| entry.value().model == model).count()
+    }
+
+    pub fn jobs(&self) -> Vec<JobSummary> {
+        self.0.iter().map(|entry| entry.value().clone()).collect()
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.jobs.remove(&self.job_id);
+    }
+}
+
+/// Outcome of a drain
+#[derive(Debug, Clone, Copy)]
+pub struct DrainSummary {
+    pub cancelled_jobs: usize,
+    pub unfinished_jobs: usize,
+}
+
+/// Stops admission and waits for outstanding work before shutdown or a rolling deploy
+#[derive(Debug, Clone)]
+pub struct Drainer {
+    pub(crate) health: Arc<Health>,
+    pub(crate) models: Arc<ModelRegistry>,
+    pub(crate) results: Arc<ResultCache<String, InferenceRunReply>>,
+    pub(crate) in_flight: InFlight,
+    pub(crate) grace_period: Duration,
+    pub(crate) cancel_queued: bool,
+}
+
+impl Drainer {
+    /// Drain using the configured grace period and queue policy
+    pub async fn drain(&self) -> DrainSummary {
+        self.drain_with(self.grace_period, self.cancel_queued).await
+    }
+
+    /// Stop admitting requests and wait up to `grace_period` for running (and,
+    /// unless `cancel_queued` is set, queued) jobs to finish. Jobs still queued at
+    /// the deadline are marked CANCELLED so clients polling for results get an answer.
+    pub async fn drain_with(&self, grace_period: Duration, cancel_queued: bool) -> DrainSummary {
+        self.health.set_draining().await;
+        info!("Draining: waiting up to {:?} for outstanding jobs", grace_period);
+
+        let mut cancelled_jobs = 0;
+        if cancel_queued {
+            cancelled_jobs += self.cancel_queued_jobs().await;
+        }
+
+        let deadline = Instant::now() + grace_period;
+        let mut interval = time::interval(Duration::from_millis(100));
+
+        loop {
+            interval.tick().await;
+
+            let mut queued = 0;
+            for slot in self.models.iter() {
+                queued += slot.queue.lock().await.len();
+            }
+            let running = self.in_flight.count();
+            if queued == 0 && running == 0 {
+                break;
+            }
+
+            if Instant::now() >= deadline {
+                warn!("Grace period expired with {} running and {} queued jobs", running, queued);
+                break;
+            }
+        }
+
+        cancelled_jobs += self.cancel_queued_jobs().await;
+        let unfinished_jobs = self.in_flight.count();
+
+        info!(
+            "Drain complete: {} jobs cancelled, {} jobs unfinished",
+            cancelled_jobs, unfinished_jobs
+        );
+
+        DrainSummary { cancelled_jobs, unfinished_jobs }
+    }
+
+    /// Wait up to `grace_period` for one model's running and queued jobs,
+    /// cancelling any still queued at the deadline. Callers stop new requests
+    /// for the model first with [`ModelSlot::start_reloading`].
+    pub(crate) async fn drain_model(&self, slot: &ModelSlot, grace_period: Duration) -> DrainSummary {
+        info!("Draining model {}: waiting up to {:?} for outstanding jobs", slot.name, grace_period);
+
+        let deadline = Instant::now() + grace_period;
+        let mut interval = time::interval(Duration::from_millis(100));
+
+        loop {
+            interval.tick().await;
+
+            let queued = slot.queue.lock().await.len();
+            let running = self.in_flight.count_model(&slot.name);
+            if queued == 0 && running == 0 {
+                break;
+            }
+
+            if Instant::now() >= deadline {
+                warn!("Grace period expired with {} running and {} queued jobs for model {}", running, queued, slot.name);
+                break;
+            }
+        }
+
+        DrainSummary {
+            cancelled_jobs: self.cancel_queue(slot).await,
+            unfinished_jobs: self.in_flight.count_model(&slot.name),
+        }
+    }
+
+    async fn cancel_queued_jobs(&self) -> usize {
+        let mut cancelled = 0;
+        for slot in self.models.iter() {
+            cancelled += self.cancel_queue(slot).await;
+        }
+        cancelled
+    }
+
+    async fn cancel_queue(&self, slot: &ModelSlot) -> usize {
+        let mut queue = slot.queue.lock().await;
+        let mut cancelled = 0;
+
+        while let Some(queued_request) = queue.dequeue().await {
+            let job_id = queued_request.job.job_id;
+            queued_request.access.finish("CANCELLED", None);
+
+            self.results.insert(job_id.clone(), queued_request.job.owner, InferenceRunReply {
+                response: None,
+                status: "CANCELLED".to_string(),
+                uuid: job_id,
+            });
+            cancelled += 1;
+        }
+
+        cancelled
+    }
+}
+
+/// Resolves on the first SIGTERM or SIGINT
+pub async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to install SIGTERM handler");
+
+    tokio::select! {
+        _ = terminate.recv() => info!("Received SIGTERM"),
+        _ = tokio::signal::ctrl_c() => info!("Received SIGINT"),
+    }
+}
@@ -0,0 +1,243 @@
+use anyhow::{bail, Context, Result};
+use cylon_config::CylonConfig;
+use std::fs::File;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_stream::wrappers::ReceiverStream;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+#[allow(unused_imports)]
+use tracing::{info, debug, error, warn};
+
+/// Connections that have not finished the TLS handshake by then are dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// TLS files and client verification settings for the gRPC listener
+#[derive(Debug, Clone)]
+pub struct TlsSettings {
+    cert: PathBuf,
+    key: PathBuf,
+    client_ca: Option<PathBuf>,
+    allowed_cns: Vec<String>,
+    reload_interval: Duration,
+}
+
+impl TlsSettings {
+    /// Read the TLS settings from the config, returning `None` when TLS is disabled
+    pub fn from_config(config: &CylonConfig) -> Result<Option<Self>> {
+        let (cert, key) = match (&config.tls_cert, &config.tls_key) {
+            (Some(cert), Some(key)) => (cert, key),
+            (None, None) => {
+                if config.tls_client_ca.is_some() || !config.tls_allowed_cns.is_empty() {
+                    bail!("tls_client_ca and tls_allowed_cns require tls_cert and tls_key");
+                }
+                return Ok(None);
+            }
+            _ => bail!("tls_cert and tls_key must be set together"),
+        };
+
+        if !config.tls_allowed_cns.is_empty() && config.tls_client_ca.is_none() {
+            bail!("tls_allowed_cns requires tls_client_ca");
+        }
+
+        Ok(Some(TlsSettings {
+            cert: PathBuf::from(cert),
+            key: PathBuf::from(key),
+            client_ca: config.tls_client_ca.as_ref().map(PathBuf::from),
+            allowed_cns: config.tls_allowed_cns.clone(),
+            reload_interval: Duration::from_secs(config.tls_reload_interval.max(1)),
+        }))
+    }
+
+    fn load(&self) -> Result<ServerConfig> {
+        let certs = load_certs(&self.cert)?;
+        let key = load_key(&self.key)?;
+
+        let builder = match &self.client_ca {
+            Some(client_ca) => {
+                let mut roots = RootCertStore::empty();
+                for cert in load_certs(client_ca)? {
+                    roots.add(cert).with_context(|| format!("Invalid CA certificate in {}", client_ca.display()))?;
+                }
+                let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                    .build()
+                    .context("Failed to build client certificate verifier")?;
+                ServerConfig::builder().with_client_cert_verifier(verifier)
+            }
+            None => ServerConfig::builder().with_no_client_auth(),
+        };
+
+        let mut server_config = builder
+            .with_single_cert(certs, key)
+            .with_context(|| format!("Certificate {} does not match key {}", self.cert.display(), self.key.display()))?;
+        server_config.alpn_protocols = vec![b"h2".to_vec()];
+
+        Ok(server_config)
+    }
+
+    /// Modification times of every configured file, used to detect rotation
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        [Some(&self.cert), Some(&self.key), self.client_ca.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).with_context(|| format!("Failed to open certificate file {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse certificates in {}", path.display()))?;
+
+    if certs.is_empty() {
+        bail!("No certificates found in {}", path.display());
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).with_context(|| format!("Failed to open key file {}", path.display()))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to parse private key in {}", path.display()))?
+        .with_context(|| format!("No private key found in {}", path.display()))
+}
+
+/// Terminates TLS for the gRPC server, picking up rotated certificates without a restart
+#[derive(Clone)]
+pub struct TlsListener {
+    settings: Arc<TlsSettings>,
+    acceptor: Arc<RwLock<TlsAcceptor>>,
+}
+
+impl TlsListener {
+    pub fn new(settings: TlsSettings) -> Result<Self> {
+        let acceptor = TlsAcceptor::from(Arc::new(settings.load()?));
+        info!(
+            "TLS enabled with certificate {}{}",
+            settings.cert.display(),
+            if settings.client_ca.is_some() { ", client certificates required" } else { "" }
+        );
+
+        Ok(TlsListener {
+            settings: Arc::new(settings),
+            acceptor: Arc::new(RwLock::new(acceptor)),
+        })
+    }
+
+    /// Poll the TLS files and swap in a new config when they change
+    ///
+    /// A failed reload keeps serving with the previous config and is retried on the next check,
+    /// so a certificate and key written one after the other are picked up once both are in place.
+    pub fn watch(&self) {
+        let listener = self.clone();
+        tokio::spawn(async move {
+            let mut last_modified = listener.settings.modified();
+            let mut interval = tokio::time::interval(listener.settings.reload_interval);
+            interval.tick().await;
+
+            loop {
+                interval.tick().await;
+                let modified = listener.settings.modified();
+                if modified == last_modified {
+                    continue;
+                }
+
+                match listener.settings.load() {
+                    Ok(server_config) => {
+                        *listener.acceptor.write().unwrap() = TlsAcceptor::from(Arc::new(server_config));
+                        last_modified = modified;
+                        info!("Reloaded TLS certificates");
+                    }
+                    Err(e) => error!("Failed to reload TLS certificates, keeping previous ones: {:#}", e),
+                }
+            }
+        });
+    }
+
+    /// Accept connections on `listener`, yielding those that complete the handshake and pass the CN check
+    pub fn incoming(self, listener: TcpListener) -> ReceiverStream<std::io::Result<TlsStream<TcpStream>>> {
+        let (tx, rx) = mpsc::channel(128);
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, remote_addr) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        warn!("Failed to accept connection: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+
+                // Handshake off the accept loop so a slow client cannot stall others
+                let acceptor = self.acceptor.read().unwrap().clone();
+                let settings = Arc::clone(&self.settings);
+                let conn_tx = tx.clone();
+                tokio::spawn(async move {
+                    let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => stream,
+                        Ok(Err(e)) => {
+                            debug!("TLS handshake with {} failed: {}", remote_addr, e);
+                            return;
+                        }
+                        Err(_) => {
+                            debug!("TLS handshake with {} timed out", remote_addr);
+                            return;
+                        }
+                    };
+
+                    if let Err(e) = check_client_cn(&settings, &stream, remote_addr) {
+                        warn!("{}", e);
+                        return;
+                    }
+
+                    let _ = conn_tx.send(Ok(stream)).await;
+                });
+
+                if tx.is_closed() {
+                    break;
+                }
+            }
+        });
+
+        ReceiverStream::new(rx)
+    }
+}
+
+fn check_client_cn(settings: &TlsSettings, stream: &TlsStream<TcpStream>, remote_addr: SocketAddr) -> Result<()> {
+    if settings.allowed_cns.is_empty() {
+        return Ok(());
+    }
+
+    let cn = stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .and_then(|cert| common_name(cert));
+
+    match cn {
+        Some(cn) if settings.allowed_cns.contains(&cn) => Ok(()),
+        Some(cn) => bail!("Rejected client {}: certificate CN {:?} is not allowed", remote_addr, cn),
+        None => bail!("Rejected client {}: certificate has no CN", remote_addr),
+    }
+}
+
+/// Subject common name of a DER-encoded certificate
+pub(crate) fn common_name(cert: &CertificateDer<'_>) -> Option<String> {
+    let (_, cert) = X509Certificate::from_der(cert).ok()?;
+    let cn = cert.subject().iter_common_name().next()?.as_str().ok()?;
+    Some(cn.to_string())
+}
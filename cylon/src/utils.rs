@@ -1,14 +1,122 @@
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use anyhow::{Context, Result};
+use cylon::observability::{build_log_filter, LogFilterHandle};
+use cylon_config::{CylonConfig, LogFormat, LogRotation};
+use opentelemetry::{global, trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace::TracerProvider, Resource};
+use std::path::Path;
+use tracing::Subscriber;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, Layer};
 
-// TODO: Set a dynamic LOG_LEVEL environment variable to support error / warn / info / debug / trace
-pub fn init_logging(debug: bool) {
-    let base_level = if debug { "debug" } else { "info" };
-    
-    let filter = EnvFilter::new(base_level)
-        .add_directive("tokenizers::tokenizer::serialization=error".parse().unwrap());
+/// Logging state that must outlive the server
+pub struct Logging {
+    pub filter_handle: LogFilterHandle,
+    tracer_provider: Option<TracerProvider>,
+    _file_guard: Option<WorkerGuard>,
+}
+
+impl Logging {
+    /// Flush pending spans and buffered log lines
+    pub fn shutdown(self) -> Result<()> {
+        if let Some(provider) = self.tracer_provider {
+            provider.shutdown()?;
+        }
+        Ok(())
+    }
+}
+
+/// Initialize logging and, when an OTLP endpoint is configured, trace export
+///
+/// The filter is taken from `log_level`, then `RUST_LOG`, then the `debug` flag.
+pub fn init_logging(config: &CylonConfig) -> Result<Logging> {
+    let directives = match &config.log_level {
+        Some(level) => level.clone(),
+        None => std::env::var("RUST_LOG")
+            .unwrap_or_else(|_| String::from(if config.debug { "debug" } else { "info" })),
+    };
+    let filter = build_log_filter(&directives)
+        .with_context(|| format!("Invalid log filter directives: {}", directives))?;
+    let (filter, filter_handle) = reload::Layer::new(filter);
+
+    let (file_layer, file_guard) = match &config.log_file {
+        Some(log_file) => {
+            let (writer, guard) = tracing_appender::non_blocking(log_file_appender(log_file, &config.log_rotation)?);
+            (Some(fmt_layer(&config.log_format, writer, false)), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    let tracer_provider = config.otlp_endpoint.as_deref().map(init_tracer_provider).transpose()?;
+    let otel_layer = tracer_provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer("cylon")));
 
     tracing_subscriber::registry()
         .with(filter)
-        .with(tracing_subscriber::fmt::layer().json()) // JSON output
+        .with(fmt_layer(&config.log_format, std::io::stdout, true))
+        .with(file_layer)
+        .with(otel_layer)
         .init();
-}
\ No newline at end of file
+
+    Ok(Logging {
+        filter_handle,
+        tracer_provider,
+        _file_guard: file_guard,
+    })
+}
+
+fn fmt_layer<S, W>(format: &LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(ansi);
+
+    match format {
+        LogFormat::Json => layer.json().boxed(),
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+    }
+}
+
+fn log_file_appender(log_file: &str, rotation: &LogRotation) -> Result<RollingFileAppender> {
+    let path = Path::new(log_file);
+    let directory = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("Log file path has no file name: {}", log_file))?;
+
+    let rotation = match rotation {
+        LogRotation::Never => Rotation::NEVER,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+    };
+
+    RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(file_name.to_string_lossy())
+        .build(directory)
+        .with_context(|| format!("Failed to open log file: {}", log_file))
+}
+
+fn init_tracer_provider(endpoint: &str) -> Result<TracerProvider> {
+    let exporter = SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new(vec![KeyValue::new("service.name", "cylon")]))
+        .build();
+
+    // Incoming gRPC metadata carries W3C `traceparent` / `tracestate` headers
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    global::set_tracer_provider(provider.clone());
+
+    Ok(provider)
+}